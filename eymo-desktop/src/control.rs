@@ -0,0 +1,64 @@
+use std::io::BufRead;
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use tracing::warn;
+
+// Read param updates for the running config from stdin, one `<name>=<value>`
+// per line, eg. `intensity=2.5`. Updates are picked up between frames.
+pub fn read_params_from_stdin() -> Receiver<(String, f32)> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_param(&line) {
+                Ok(update) => {
+                    if tx.send(update).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("{e}"),
+            }
+        }
+    });
+
+    rx
+}
+
+pub fn parse_param(line: &str) -> Result<(String, f32), String> {
+    let (name, value) = line
+        .split_once('=')
+        .ok_or_else(|| format!("expected <name>=<value>, got `{line}`"))?;
+    let value = value
+        .trim()
+        .parse::<f32>()
+        .map_err(|e| format!("invalid value for `{}`: {e}", name.trim()))?;
+    Ok((name.trim().to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_param() {
+        assert_eq!(parse_param("speed=0.5"), Ok(("speed".to_string(), 0.5)));
+        assert_eq!(
+            parse_param("  speed =\t-2 "),
+            Ok(("speed".to_string(), -2.))
+        );
+        assert_eq!(
+            parse_param("speed 0.5"),
+            Err("expected <name>=<value>, got `speed 0.5`".to_string())
+        );
+        assert_eq!(
+            parse_param(" speed = fast"),
+            Err("invalid value for `speed`: invalid float literal".to_string())
+        );
+    }
+}
@@ -1,6 +1,6 @@
 #![warn(unused_extern_crates)]
 use anyhow::{Error, Result};
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
 use eymo_img::imggpu::gpu::GpuExecutor;
 use eymo_img::imggpu::rgb;
 use eymo_img::lang;
@@ -8,14 +8,16 @@ use eymo_img::pipeline::{Detection, Pipeline};
 use image::RgbaImage;
 use nokhwa::pixel_format::RgbAFormat;
 use pollster::FutureExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use tracing::{Level, debug, error, span, trace, warn};
+use tracing::{Level, debug, error, info, span, trace, warn};
 use tracing_subscriber;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt::format::FmtSpan;
 use video::{OutputVideoStream, create_input_stream};
 
+mod control;
+mod reload;
 mod video;
 
 #[derive(Parser, Debug)]
@@ -29,7 +31,8 @@ struct CmdArgs {
     #[arg(long, default_value = "30")]
     fps: u32,
 
-    /// Config file to read from
+    /// Config file to read from. Included files are relative to it. Changes
+    /// are picked up while running
     #[arg(
         short,
         long,
@@ -44,6 +47,43 @@ struct CmdArgs {
     /// Process single input frame, reading from input path
     #[arg(short, long, requires = "output")]
     input: Option<PathBuf>,
+
+    /// Set a `param` declared by the config, eg. intensity=2. Params can
+    /// also be set while running by writing the same to stdin
+    #[arg(short, long = "param", value_name = "NAME=VALUE", value_parser = control::parse_param)]
+    param: Vec<(String, f32)>,
+
+    /// Log transforms that are skipped or have operations ignored, such as
+    /// a face index that isn't detected
+    #[arg(long)]
+    strict: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a config file in canonical format. Comments are not preserved
+    Fmt {
+        file: PathBuf,
+
+        /// Overwrite the file instead of printing it
+        #[arg(short, long)]
+        write: bool,
+    },
+    /// Report operations in a config file that won't run as written
+    Check {
+        file: PathBuf,
+
+        /// Frame size to check shapes against, eg. 1280x720
+        #[arg(long, value_parser = parse_frame_size)]
+        frame: Option<(u32, u32)>,
+
+        /// Most faces expected in a frame, to check face indices against
+        #[arg(long)]
+        faces: Option<u32>,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -68,9 +108,26 @@ fn main() -> Result<()> {
 
     let args = CmdArgs::parse();
 
+    match args.command {
+        Some(Command::Fmt { file, write }) => return format_config(&file, write),
+        Some(Command::Check { file, frame, faces }) => {
+            let options = lang::LintOptions {
+                frame,
+                max_faces: faces,
+            };
+            return check_config(&file, &options);
+        }
+        None => {}
+    }
+
     let mut pipeline = Pipeline::new()?;
     let mut gpu = GpuExecutor::new()?;
-    let mut interpreter = lang::parse(&std::fs::read_to_string(args.config)?, &mut gpu)?;
+    let mut watcher = reload::ConfigWatcher::new(args.config.clone());
+    let mut interpreter = load_config(&mut watcher, &mut gpu)?;
+    for (name, value) in &args.param {
+        interpreter.set_param(name, *value)?;
+    }
+    interpreter.set_strict(args.strict);
 
     if args.out.output.is_some() {
         // Process single image at file and exit
@@ -89,11 +146,38 @@ fn main() -> Result<()> {
     let mut output_stream =
         OutputVideoStream::new(resolution.width(), resolution.height(), args.out.device)?;
 
+    let params = control::read_params_from_stdin();
     let mut detection_cache = None;
+    let mut last_report = None;
     loop {
         let span = span!(Level::INFO, "frame_loop_iter");
         let _guard = span.enter();
 
+        match watcher.poll() {
+            Some(Ok(statements)) => {
+                if let Err(e) = load_luts(&statements, &mut gpu) {
+                    error!("{e:?}");
+                }
+                interpreter.reload(statements, &mut gpu);
+                info!("Reloaded {}", watcher.path().display());
+            }
+            Some(Err(e)) => error!(
+                line = e.line,
+                column = e.column,
+                "Failed to reload {}, keeping the previous config\n{}",
+                watcher.path().display(),
+                e.render()
+            ),
+            None => {}
+        }
+
+        for (name, value) in params.try_iter() {
+            match interpreter.set_param(&name, value) {
+                Ok(_) => debug!("Set {name} to {value}"),
+                Err(e) => warn!("{e}"),
+            }
+        }
+
         let get_frame_span = span!(Level::DEBUG, "get_frame");
         let get_frame_guard = get_frame_span.enter();
         let result = camera.frame();
@@ -129,6 +213,7 @@ fn main() -> Result<()> {
                     Err(e) => error!("Failed to render frame: {e:?}"),
                 }
                 drop(write_frame_guard);
+                log_report(&interpreter, &mut last_report);
             }
             Err(e) => error!("Failed to process frame: {e:?}"),
         }
@@ -139,6 +224,99 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn load_config(
+    watcher: &mut reload::ConfigWatcher,
+    gpu: &mut GpuExecutor,
+) -> Result<lang::Interpreter> {
+    match watcher.load() {
+        Ok(statements) => {
+            load_luts(&statements, gpu)?;
+            Ok(lang::Interpreter::new(statements, gpu))
+        }
+        Err(e) => {
+            eprintln!("{}", e.render());
+            Err(Error::msg(format!(
+                "Failed to parse {}",
+                watcher.path().display()
+            )))
+        }
+    }
+}
+
+// Load the `.cube` files for `lut` operations, whose paths were resolved
+// relative to the file using them while parsing
+fn load_luts(statements: &[lang::ast::Statement], gpu: &mut GpuExecutor) -> Result<()> {
+    for path in lang::lut_paths(statements) {
+        let src = std::fs::read_to_string(&path)
+            .map_err(|e| Error::msg(format!("Failed to read lut {path}: {e}")))?;
+        gpu.load_lut(&path, &src)?;
+    }
+    Ok(())
+}
+
+fn format_config(path: &Path, write: bool) -> Result<()> {
+    let src = std::fs::read_to_string(path)?;
+    let formatted = match lang::format_source(&src) {
+        Ok(formatted) => formatted,
+        Err(mut e) => {
+            e.file = Some(path.display().to_string());
+            eprintln!("{}", e.render());
+            return Err(Error::msg(format!("Failed to parse {}", path.display())));
+        }
+    };
+
+    match write {
+        // formatting drops comments, which shouldn't be lost from the file
+        true if lang::has_comments(&src) => {
+            return Err(Error::msg(format!(
+                "Not overwriting {}, as formatting would remove its comments",
+                path.display()
+            )));
+        }
+        true => std::fs::write(path, formatted)?,
+        false => print!("{formatted}"),
+    }
+    Ok(())
+}
+
+fn check_config(path: &Path, options: &lang::LintOptions) -> Result<()> {
+    let findings = match lang::lint_file(&path.to_string_lossy(), &mut lang::FsResolver, options) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("{}", e.render());
+            return Err(Error::msg(format!("Failed to parse {}", path.display())));
+        }
+    };
+
+    for f in &findings {
+        eprintln!("{}", f.render());
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == lang::Severity::Error)
+        .count();
+    match errors {
+        0 => Ok(()),
+        n => Err(Error::msg(format!(
+            "Found {n} error(s) in {}",
+            path.display()
+        ))),
+    }
+}
+
+// `<width>x<height>`, eg. 1280x720
+fn parse_frame_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected <width>x<height>, got `{s}`"))?;
+    let parse = |n: &str| {
+        n.parse::<u32>()
+            .map_err(|e| format!("invalid frame size `{s}`: {e}"))
+    };
+    Ok((parse(w)?, parse(h)?))
+}
+
 fn process_image(
     src: PathBuf,
     dest: PathBuf,
@@ -149,6 +327,7 @@ fn process_image(
 ) -> Result<()> {
     let img: RgbaImage = image::open(src)?.into();
     let result = process_frame(img, gpu, pipeline, interpreter, &mut None, within_ms)?;
+    log_report(interpreter, &mut None);
     result.save(dest)?;
     Ok(())
 }
@@ -197,6 +376,25 @@ fn process_frame(
     Ok(img)
 }
 
+// Log what strict mode skipped, only when it changes so the same problems
+// aren't repeated every frame
+fn log_report(interpreter: &lang::Interpreter, last: &mut Option<lang::Report>) {
+    let Some(report) = interpreter.report() else {
+        return;
+    };
+    if last.as_ref() == Some(report) {
+        return;
+    }
+
+    for skipped in &report.skipped {
+        warn!("Skipped {skipped}");
+    }
+    if report.skipped.is_empty() && last.is_some() {
+        info!("Nothing skipped");
+    }
+    last.replace(report.clone());
+}
+
 fn check_time(within_ms: Option<u32>, start: Instant, waypoint: &str) -> Result<()> {
     let elapsed_ms = start.elapsed().as_millis();
     debug!("{elapsed_ms}ms at {waypoint}");
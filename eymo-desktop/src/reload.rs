@@ -0,0 +1,131 @@
+use eymo_img::lang::{self, FsResolver, ParseError, Resolver, ast::Statement};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often to look for changes to the config
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Watches a config file, along with everything it includes, for changes
+pub struct ConfigWatcher {
+    path: PathBuf,
+    // files read by the last load, and when they were last modified
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            files: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Parse the config, watching every file read along the way and the
+    // `.cube` files its luts use. Files are watched even if parsing fails,
+    // so fixing them triggers a reload.
+    pub fn load(&mut self) -> Result<Vec<Statement>, Box<ParseError>> {
+        let mut resolver = RecordingResolver::default();
+        let result = lang::parse_file(&self.path.to_string_lossy(), &mut resolver);
+
+        let luts = match &result {
+            Ok(statements) => lang::lut_paths(statements),
+            Err(_) => Vec::new(),
+        };
+        self.files = resolver
+            .paths
+            .into_iter()
+            .chain(luts.into_iter().map(PathBuf::from))
+            .map(|p| {
+                let modified = modified(&p);
+                (p, modified)
+            })
+            .collect();
+        result
+    }
+
+    // Reparsed config if any of its files changed since it was last loaded
+    pub fn poll(&mut self) -> Option<Result<Vec<Statement>, Box<ParseError>>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let changed = self
+            .files
+            .iter()
+            .any(|(path, last_modified)| modified(path) != *last_modified);
+        changed.then(|| self.load())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Reads files from disk, keeping track of which were asked for
+#[derive(Default)]
+struct RecordingResolver {
+    paths: Vec<PathBuf>,
+}
+
+impl Resolver for RecordingResolver {
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        self.paths.push(PathBuf::from(path));
+        FsResolver.load(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Mark a file as changed, whatever the resolution of the filesystem's
+    // modification times
+    fn touch(path: &Path) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|f| f.set_modified(SystemTime::UNIX_EPOCH))
+            .unwrap();
+    }
+
+    fn poll(watcher: &mut ConfigWatcher) -> Option<Result<Vec<Statement>, Box<ParseError>>> {
+        watcher.last_poll = Instant::now() - POLL_INTERVAL;
+        watcher.poll()
+    }
+
+    #[test]
+    fn reloads_included_files() {
+        let dir = std::env::temp_dir().join(format!("eymo-reload-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        let config = dir.join("main.eymo");
+        let lib = dir.join("lib/pop.eymo");
+        let cube = dir.join("lib/film.cube");
+        fs::write(&config, "include \"lib/pop.eymo\"\nface: pop").unwrap();
+        fs::write(&lib, "let pop = scale(2), lut(\"film.cube\")").unwrap();
+        fs::write(&cube, "LUT_3D_SIZE 2").unwrap();
+
+        let mut watcher = ConfigWatcher::new(config);
+        let loaded = watcher.load().unwrap();
+        assert!(poll(&mut watcher).is_none());
+
+        fs::write(&lib, "let pop = scale(3), lut(\"film.cube\")").unwrap();
+        touch(&lib);
+        let reloaded = poll(&mut watcher).unwrap().unwrap();
+        assert_ne!(reloaded, loaded);
+        assert!(poll(&mut watcher).is_none());
+
+        // luts are read outside of parsing, but edits to them still reload
+        touch(&cube);
+        assert_eq!(poll(&mut watcher).unwrap().unwrap(), reloaded);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+@group(0) @binding(0) var input_tex : texture_2d<f32>;
+@group(0) @binding(1) var samp : sampler;
+@group(0) @binding(2) var<uniform> params : BlurParams;
+
+struct BlurParams {
+	// one pixel along the pass's axis, (1, 0) or (0, 1)
+	direction: vec2f,
+	// pixels either side of the center to sample
+	radius: f32,
+}
+
+struct VertexIn {
+  @location(0) position : vec2f,
+	@location(1) tex_coord: vec2f,
+}
+
+struct VertexOut {
+  @builtin(position) position : vec4f,
+}
+
+@vertex fn vert_main(in : VertexIn) -> VertexOut {
+  var out : VertexOut;
+  out.position = vec4f(in.position, 0., 1.);
+  return out;
+}
+
+// One pass of a separable gaussian blur, sampling the pixel under the
+// fragment and its neighbours along the pass's axis
+@fragment fn frag_main(pos : VertexOut) -> @location(0) vec4f {
+	let dims = vec2f(textureDimensions(input_tex));
+	let sigma = max(params.radius / 2., 0.5);
+	let r = i32(ceil(params.radius));
+
+	var total = vec4f(0., 0., 0., 0.);
+	var weights = 0.;
+	for (var i = -r; i <= r; i++) {
+			let x = f32(i);
+			let weight = exp(-(x * x) / (2. * sigma * sigma));
+			let coord = (pos.position.xy + params.direction * x) / dims;
+			total += textureSampleLevel(input_tex, samp, coord, 0.) * weight;
+			weights += weight;
+	}
+
+	return total / weights;
+}
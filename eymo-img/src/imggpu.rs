@@ -1,4 +1,5 @@
 pub mod gpu;
+pub mod lut;
 pub mod resize;
 pub mod rgb;
 pub mod util;
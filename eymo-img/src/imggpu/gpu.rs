@@ -1,5 +1,6 @@
+use super::lut::{Cube, Lut};
 use super::util::padded_bytes_per_row;
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use image::{DynamicImage, RgbaImage};
 #[cfg(not(target_arch = "wasm32"))]
 use pollster::FutureExt;
@@ -13,6 +14,8 @@ pub struct GpuExecutor {
     pub queue: wgpu::Queue,
     pub device: wgpu::Device,
     shaders: HashMap<String, wgpu::ShaderModule>,
+    // colour lookup tables for `lut` operations, by path as written
+    luts: HashMap<String, Lut>,
 }
 
 impl GpuExecutor {
@@ -43,6 +46,7 @@ impl GpuExecutor {
             device,
             queue,
             shaders: HashMap::new(),
+            luts: HashMap::new(),
         })
     }
 
@@ -104,6 +108,7 @@ impl GpuExecutor {
                 device,
                 queue,
                 shaders: HashMap::new(),
+                luts: HashMap::new(),
             },
             surface,
             config,
@@ -119,6 +124,19 @@ impl GpuExecutor {
         self.shaders.get(name).unwrap().clone()
     }
 
+    // Parse the `.cube` file `src` and load it onto the GPU for `lut`
+    // operations naming `path`, replacing any lut already loaded for it
+    pub fn load_lut(&mut self, path: &str, src: &str) -> Result<()> {
+        let cube = Cube::parse(src).map_err(|e| anyhow!("invalid lut {path}: {e}"))?;
+        let lut = cube.load(self);
+        self.luts.insert(path.to_string(), lut);
+        Ok(())
+    }
+
+    pub fn lut(&self, path: &str) -> Option<&Lut> {
+        self.luts.get(path)
+    }
+
     #[allow(unused)]
     pub fn snapshot_texture(&self, tex: &wgpu::Texture, fname: &str) -> Result<()> {
         let width = tex.width();
@@ -0,0 +1,225 @@
+use super::gpu::GpuExecutor;
+use anyhow::{Result, anyhow};
+use half::f16;
+use wgpu::util::DeviceExt;
+
+// 3D colour lookup table, as read from an Adobe `.cube` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cube {
+    // entries along each side of the table
+    pub size: u32,
+    // input colours mapped to the first and last entries on each axis
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    // output colours, red changing fastest then green then blue
+    pub table: Vec<[f32; 3]>,
+}
+
+// A cube loaded onto the GPU, ready to sample in the transform shader
+#[derive(Debug, Clone)]
+pub struct Lut {
+    pub(crate) texture: wgpu::Texture,
+    // `LutParams` for the cube's domain
+    pub(crate) params: wgpu::Buffer,
+}
+
+// Maps colours to texture coordinates of the lut, as `colour * scale +
+// offset`, so the domain's corners land on the centers of corner texels
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LutParams {
+    pub(crate) scale: [f32; 4],
+    pub(crate) offset: [f32; 4],
+}
+
+// Larger tables are rare, and take a lot of texture memory
+const MAX_CUBE_SIZE: u32 = 256;
+
+impl Cube {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut size = None;
+        let mut domain_min = [0., 0., 0.];
+        let mut domain_max = [1., 1., 1.];
+        let mut table = Vec::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |message: String| anyhow!("line {}: {message}", i + 1);
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let floats = |words: std::str::SplitWhitespace| -> Result<[f32; 3]> {
+                let values = words
+                    .map(|w| w.parse::<f32>().map_err(|e| err(format!("`{w}`: {e}"))))
+                    .collect::<Result<Vec<_>>>()?;
+                values
+                    .try_into()
+                    .map_err(|_| err("expected 3 numbers".to_string()))
+            };
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let n = words
+                        .next()
+                        .and_then(|w| w.parse::<u32>().ok())
+                        .filter(|n| (2..=MAX_CUBE_SIZE).contains(n))
+                        .ok_or_else(|| {
+                            err(format!("LUT_3D_SIZE must be from 2 to {MAX_CUBE_SIZE}"))
+                        })?;
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => return Err(err("1D luts aren't supported".to_string())),
+                "DOMAIN_MIN" => domain_min = floats(words)?,
+                "DOMAIN_MAX" => domain_max = floats(words)?,
+                _ => table.push(floats(line.split_whitespace())?),
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("missing LUT_3D_SIZE"))?;
+        if table.len() != size.pow(3) as usize {
+            return Err(anyhow!(
+                "expected {} table entries for LUT_3D_SIZE {size}, found {}",
+                size.pow(3),
+                table.len()
+            ));
+        }
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err(anyhow!("DOMAIN_MAX must be above DOMAIN_MIN"));
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    // Table leaving colours as they are
+    pub fn identity(size: u32) -> Self {
+        let step = |i: u32| i as f32 / (size - 1) as f32;
+        let table = (0..size.pow(3))
+            .map(|i| [step(i % size), step(i / size % size), step(i / size / size)])
+            .collect();
+        Self {
+            size,
+            domain_min: [0., 0., 0.],
+            domain_max: [1., 1., 1.],
+            table,
+        }
+    }
+
+    pub(crate) fn params(&self) -> LutParams {
+        let n = self.size as f32;
+        let mut scale = [0.; 4];
+        let mut offset = [0.; 4];
+        for c in 0..3 {
+            scale[c] = (n - 1.) / (n * (self.domain_max[c] - self.domain_min[c]));
+            offset[c] = 0.5 / n - self.domain_min[c] * scale[c];
+        }
+        LutParams { scale, offset }
+    }
+
+    pub fn load(&self, gpu: &GpuExecutor) -> Lut {
+        let size = wgpu::Extent3d {
+            width: self.size,
+            height: self.size,
+            depth_or_array_layers: self.size,
+        };
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lut_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // 8 bits per channel bands smooth gradients once graded
+            format: wgpu::TextureFormat::Rgba16Float,
+            view_formats: &[wgpu::TextureFormat::Rgba16Float],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let texels = self
+            .table
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.].map(f16::from_f32))
+            .collect::<Vec<f16>>();
+        gpu.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.size * 8),
+                rows_per_image: Some(self.size),
+            },
+            size,
+        );
+
+        let params = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("lut_params"),
+                contents: bytemuck::cast_slice(&[self.params()]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        Lut { texture, params }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cube() {
+        let src = "# a comment\nTITLE \"swap red and blue\"\nLUT_3D_SIZE 2\n\n\
+                   0 0 0\n0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+        let cube = Cube::parse(src).unwrap();
+        assert_eq!(cube.size, 2);
+        assert_eq!(cube.domain_min, [0., 0., 0.]);
+        assert_eq!(cube.table[1], [0., 0., 1.]);
+        assert_eq!(Cube::identity(2).table[1], [1., 0., 0.]);
+        assert_eq!(
+            cube.params(),
+            LutParams {
+                scale: [0.5, 0.5, 0.5, 0.],
+                offset: [0.25, 0.25, 0.25, 0.],
+            }
+        );
+
+        let src = "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n".to_string()
+            + &"0.5 0.5 0.5\n".repeat(8);
+        let cube = Cube::parse(&src).unwrap();
+        assert_eq!(cube.params().scale, [0.25, 0.25, 0.25, 0.]);
+    }
+
+    #[test]
+    fn parse_cube_errors() {
+        let err = |src: &str| Cube::parse(src).unwrap_err().to_string();
+        assert_eq!(err("0 0 0\n"), "missing LUT_3D_SIZE");
+        assert_eq!(
+            err("LUT_3D_SIZE 2\n0 0 0\n"),
+            "expected 8 table entries for LUT_3D_SIZE 2, found 1"
+        );
+        assert_eq!(err("LUT_3D_SIZE 2\n0 0\n"), "line 2: expected 3 numbers");
+        assert_eq!(
+            err("LUT_3D_SIZE 2\n0 x 0\n"),
+            "line 2: `x`: invalid float literal"
+        );
+        assert_eq!(
+            err("LUT_1D_SIZE 1024\n"),
+            "line 1: 1D luts aren't supported"
+        );
+        assert_eq!(
+            err("LUT_3D_SIZE 1\n"),
+            "line 1: LUT_3D_SIZE must be from 2 to 256"
+        );
+    }
+}
@@ -1,41 +1,398 @@
 use crate::imggpu::gpu::GpuExecutor;
 use crate::pipeline::{Detection, Face};
+use crate::shapes::polygon::Polygon;
 use crate::shapes::shape::Shape;
-use crate::transform::{ShapeOp, Transform};
-use anyhow::{Error, Result};
+use crate::transform::{ColourStep, Settings, ShapeOp, Step, Transform};
+use anyhow::{Result, anyhow};
 use ast::{Operation, Statement};
+pub use error::ParseError;
+pub use format::{format, has_comments};
+use include::SourceFile;
+pub use include::{FsResolver, NoIncludes, Resolver};
 use lalrpop_util::lalrpop_mod;
+pub use lint::{Finding, LintOptions, Severity};
+pub use report::{Reason, Report, Skipped};
+use serde_json::value::RawValue;
+use std::collections::HashMap;
 use tracing::warn;
+use web_time::Instant;
 
 pub mod ast;
+mod error;
+mod expand;
+mod format;
+mod include;
+mod lint;
+mod report;
+mod validate;
 
-lalrpop_mod!(pub parser, "/lang/grammar.rs");
+// block items are collected with their locations, as tuples clippy finds
+// too complex
+lalrpop_mod!(#[allow(clippy::type_complexity)] pub parser, "/lang/grammar.rs");
 
 pub fn parse(input: &str, gpu: &mut GpuExecutor) -> Result<Interpreter> {
-    // HACK: append newline for parser happiness
-    match parser::StatementsParser::new().parse(&(input.to_owned() + "\n")) {
-        Ok(res) => Ok(Interpreter::new(res, gpu)),
-        Err(e) => Err(Error::msg(format!("{e:?}"))),
+    Ok(Interpreter::new(parse_statements(input)?, gpu))
+}
+
+// Parse source into statements as written, including `let` bindings
+pub fn parse_ast(input: &str) -> std::result::Result<Vec<Statement>, Box<ParseError>> {
+    parser::StatementsParser::new()
+        .parse(input)
+        .map_err(|e| Box::new(ParseError::from_lalrpop(input, e)))
+}
+
+// Parse source into statements ready for interpretation, with all `let`
+// bindings substituted in
+pub fn parse_statements(input: &str) -> std::result::Result<Vec<Statement>, Box<ParseError>> {
+    parse_statements_with(input, &mut NoIncludes)
+}
+
+// Parse source into statements ready for interpretation, splicing in any
+// `include`d files loaded through `resolver`. Includes are relative to the
+// current directory.
+pub fn parse_statements_with(
+    input: &str,
+    resolver: &mut impl Resolver,
+) -> std::result::Result<Vec<Statement>, Box<ParseError>> {
+    load(
+        SourceFile {
+            name: None,
+            src: input.to_string(),
+        },
+        resolver,
+    )
+}
+
+// Parse the config file at `path`, with includes relative to the file
+pub fn parse_file(
+    path: &str,
+    resolver: &mut impl Resolver,
+) -> std::result::Result<Vec<Statement>, Box<ParseError>> {
+    load(read_file(path, resolver)?, resolver)
+}
+
+// Check a config for operations that won't run as written, splicing in any
+// `include`d files loaded through `resolver`
+pub fn lint(
+    input: &str,
+    resolver: &mut impl Resolver,
+    options: &LintOptions,
+) -> std::result::Result<Vec<Finding>, Box<ParseError>> {
+    let root = SourceFile {
+        name: None,
+        src: input.to_string(),
+    };
+    let (files, statements) = load_tagged(root, resolver)?;
+    Ok(lint::lint(&files, &statements, options))
+}
+
+// Check the config file at `path`, with includes relative to the file
+pub fn lint_file(
+    path: &str,
+    resolver: &mut impl Resolver,
+    options: &LintOptions,
+) -> std::result::Result<Vec<Finding>, Box<ParseError>> {
+    let (files, statements) = load_tagged(read_file(path, resolver)?, resolver)?;
+    Ok(lint::lint(&files, &statements, options))
+}
+
+fn read_file(
+    path: &str,
+    resolver: &mut impl Resolver,
+) -> std::result::Result<SourceFile, Box<ParseError>> {
+    let src = resolver.load(path).map_err(|e| {
+        ParseError::new("", 0, 0, format!("failed to read `{path}`: {e}")).in_file(Some(path))
+    })?;
+
+    Ok(SourceFile {
+        name: Some(path.to_string()),
+        src,
+    })
+}
+
+// Canonical formatting of config source, leaving includes and bindings
+// as written. Comments are not preserved, see `has_comments`.
+pub fn format_source(input: &str) -> std::result::Result<String, Box<ParseError>> {
+    Ok(format(&parse_ast(input)?))
+}
+
+pub fn from_json(input: &str, gpu: &mut GpuExecutor) -> Result<Interpreter> {
+    Ok(Interpreter::new(statements_from_json(input)?, gpu))
+}
+
+// Statements ready for interpretation from their JSON representation (as
+// written by `to_json`), with all `let` bindings substituted in. JSON configs
+// can't include other files.
+pub fn statements_from_json(input: &str) -> std::result::Result<Vec<Statement>, Box<ParseError>> {
+    let raw: Vec<&RawValue> =
+        serde_json::from_str(input).map_err(|e| json_error(input, input, e))?;
+    let mut statements = Vec::with_capacity(raw.len());
+    for json in raw {
+        let json = json.get();
+        let mut s: Statement =
+            serde_json::from_str(json).map_err(|e| json_error(input, json, e))?;
+        // JSON has no finer locations, so errors point at the whole statement
+        let start = json.as_ptr() as usize - input.as_ptr() as usize;
+        s.locate(ast::Span {
+            start,
+            end: start + json.len(),
+        });
+        statements.push((0, s));
     }
+
+    if let Some((_, Statement::Include(include))) = statements
+        .iter()
+        .find(|(_, s)| matches!(s, Statement::Include(_)))
+    {
+        return Err(Box::new(ParseError::new(
+            input,
+            include.span.start,
+            include.span.end,
+            "includes are not supported in JSON configs",
+        )));
+    }
+
+    let root = SourceFile {
+        name: None,
+        src: input.to_string(),
+    };
+    let statements = prepare(&[root], statements)?;
+    Ok(statements.into_iter().map(|(_, s)| s).collect())
+}
+
+// Error from deserializing `json`, part of the whole `input`
+fn json_error(input: &str, json: &str, e: serde_json::Error) -> Box<ParseError> {
+    let offset = json.as_ptr() as usize - input.as_ptr() as usize;
+    let start = offset + json_offset(json, e.line(), e.column());
+    let message = e.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(i) => &message[..i],
+        None => &message,
+    };
+    Box::new(ParseError::new(input, start, start, message))
+}
+
+// JSON representation of statements, eg. for building configs without
+// writing source
+pub fn to_json(statements: &[Statement]) -> String {
+    serde_json::to_string_pretty(statements).expect("statements are always valid JSON")
+}
+
+// Paths of the `.cube` files `lut` operations use, which need loading onto
+// the GPU with `GpuExecutor::load_lut` before they take effect. Paths from
+// config files are already relative to the file that uses them.
+pub fn lut_paths(statements: &[Statement]) -> Vec<String> {
+    let mut paths = Vec::new();
+    let transforms = statements.iter().flat_map(|s| match s {
+        Statement::Transform(t) => std::slice::from_ref(t),
+        Statement::Scene(scene) => &scene.transforms[..],
+        _ => &[],
+    });
+    for t in transforms {
+        for o in &t.operations {
+            if let Operation::Lut { path, .. } = o
+                && !paths.contains(path)
+            {
+                paths.push(path.clone());
+            }
+        }
+    }
+    paths
+}
+
+// Byte offset of a 1-based line and column reported by serde_json
+fn json_offset(input: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = input
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    line_start + column.saturating_sub(1)
+}
+
+fn load(
+    root: SourceFile,
+    resolver: &mut impl Resolver,
+) -> std::result::Result<Vec<Statement>, Box<ParseError>> {
+    let (_, statements) = load_tagged(root, resolver)?;
+    Ok(statements.into_iter().map(|(_, s)| s).collect())
+}
+
+// Like `load`, keeping track of which file each statement is from
+fn load_tagged(
+    root: SourceFile,
+    resolver: &mut impl Resolver,
+) -> std::result::Result<include::Loaded, Box<ParseError>> {
+    let (files, statements) = include::load(root, resolver)?;
+    let statements = prepare(&files, statements)?;
+    Ok((files, statements))
+}
+
+// Check statements, then substitute in their `let` bindings
+fn prepare(
+    files: &[SourceFile],
+    statements: Vec<(usize, Statement)>,
+) -> std::result::Result<Vec<(usize, Statement)>, Box<ParseError>> {
+    validate::validate(files, &statements)?;
+    let statements = expand::expand(files, statements)?;
+    validate::validate_expanded(files, &statements)?;
+    Ok(statements)
 }
 
 #[derive(Debug)]
 pub struct Interpreter {
+    scenes: Vec<Scene>,
+    // length of the timeline when it loops
+    loop_period: Option<f32>,
+    // clock for the timeline and `t` in operation expressions
+    started: Instant,
+    // where on the timeline the last frame was
+    position: f32,
+    // current value of each `param`
+    params: HashMap<String, f32>,
+    // what was skipped on the last frame, kept only in strict mode
+    report: Option<Report>,
+}
+
+// Transforms run together, either for the whole timeline or only within a
+// window of it
+#[derive(Debug)]
+struct Scene {
+    window: Option<ast::Window>,
     transforms: Vec<(Transform, ast::Transform)>,
+    active: bool,
 }
 
 impl Interpreter {
     pub fn new(statements: Vec<Statement>, gpu: &mut GpuExecutor) -> Self {
+        Self::with_transforms(statements, |cmd, _, params| {
+            (build_transform(&cmd, gpu, params), cmd)
+        })
+    }
+
+    // Switch to new statements, eg. after the config was edited. Transforms
+    // that didn't change keep their animation state, params keep their
+    // current values and the timeline carries on from where it is.
+    pub fn reload(&mut self, statements: Vec<Statement>, gpu: &mut GpuExecutor) {
+        let active = self
+            .scenes
+            .iter()
+            .filter(|s| s.active)
+            .map(|s| s.window)
+            .collect::<Vec<_>>();
+        let mut previous = std::mem::take(&mut self.scenes)
+            .into_iter()
+            .flat_map(|s| s.transforms.into_iter().map(move |t| (s.window, t)))
+            .collect::<Vec<_>>();
+
+        let mut next = Self::with_transforms(statements, |cmd, window, params| {
+            take_unchanged(&mut previous, window, &cmd)
+                .unwrap_or_else(|| (build_transform(&cmd, gpu, params), cmd))
+        });
+
+        // unchanged transforms in a running scene shouldn't start over
+        for scene in next.scenes.iter_mut() {
+            scene.active = active.contains(&scene.window);
+        }
+        for (name, value) in next.params.iter_mut() {
+            if let Some(current) = self.params.get(name) {
+                *value = *current;
+            }
+        }
+        next.started = self.started;
+        next.position = self.position;
+        next.report = self.report.take();
+        *self = next;
+    }
+
+    // `build` readies each transform to run in the scene with the given
+    // window, or for the whole timeline if there is none
+    fn with_transforms<F>(statements: Vec<Statement>, mut build: F) -> Self
+    where
+        F: FnMut(
+            ast::Transform,
+            Option<ast::Window>,
+            &HashMap<String, f32>,
+        ) -> (Transform, ast::Transform),
+    {
+        let mut scenes: Vec<Scene> = Vec::new();
+        let mut looping = false;
+        let params = statements
+            .iter()
+            .filter_map(|s| match s {
+                ast::Statement::Param(p) => Some((p.name.name.clone(), p.default)),
+                _ => None,
+            })
+            .collect();
+
+        for s in statements {
+            match s {
+                ast::Statement::Transform(t) => {
+                    let t = build(t, None, &params);
+                    // consecutive top-level transforms share an always active scene
+                    match scenes.last_mut() {
+                        Some(scene) if scene.window.is_none() => scene.transforms.push(t),
+                        _ => scenes.push(Scene {
+                            window: None,
+                            transforms: Vec::from([t]),
+                            active: false,
+                        }),
+                    }
+                }
+                ast::Statement::Scene(scene) => scenes.push(Scene {
+                    window: Some(scene.window),
+                    transforms: scene
+                        .transforms
+                        .into_iter()
+                        .map(|t| build(t, Some(scene.window), &params))
+                        .collect(),
+                    active: false,
+                }),
+                ast::Statement::Loop(_) => looping = true,
+                ast::Statement::Let(_) | ast::Statement::Param(_) | ast::Statement::Include(_) => {}
+            }
+        }
+
+        let windows = scenes.iter().filter_map(|s| s.window).collect::<Vec<_>>();
         Self {
-            transforms: statements
-                .into_iter()
-                .map(|s| match s {
-                    ast::Statement::Transform(t) => (build_transform(&t, gpu), t),
-                })
-                .collect::<Vec<_>>(),
+            scenes,
+            loop_period: looping.then(|| loop_period(&windows)).flatten(),
+            started: Instant::now(),
+            position: 0.,
+            params,
+            report: None,
+        }
+    }
+
+    // Change the value of a `param` declared by the config. Operations
+    // using it pick up the new value on the next frame, carrying on from
+    // wherever their animations are.
+    pub fn set_param(&mut self, name: &str, value: f32) -> Result<()> {
+        match self.params.get_mut(name) {
+            Some(v) => {
+                *v = value;
+                Ok(())
+            }
+            None => Err(anyhow!("unknown param `{name}`")),
         }
     }
 
+    // Current value of each `param` declared by the config
+    pub fn params(&self) -> &HashMap<String, f32> {
+        &self.params
+    }
+
+    // In strict mode each frame reports the transforms that were skipped,
+    // or had operations ignored, and why
+    pub fn set_strict(&mut self, strict: bool) {
+        self.report = strict.then(Report::default);
+    }
+
+    // What was skipped on the last frame, if in strict mode
+    pub fn report(&self) -> Option<&Report> {
+        self.report.as_ref()
+    }
+
     pub fn execute<F>(
         &mut self,
         detection: &Detection,
@@ -47,87 +404,351 @@ impl Interpreter {
         F: Fn(&str) -> Result<()>,
     {
         let mut output = tex;
+        let t = self.started.elapsed().as_secs_f32();
+        let position = timeline_position(t, self.loop_period);
+        leave_scenes(&mut self.scenes, self.position, position);
+        self.position = position;
 
-        for (idx, (transform, cmd)) in self.transforms.iter_mut().enumerate() {
-            match timeout_check(&format!("Transform {idx}")) {
-                Ok(_) => {}
-                Err(e) => {
-                    warn!("{e:?}");
-                    return output;
+        let strict = self.report.is_some();
+        let mut report = strict.then(Report::default);
+        let mut skip = |index: usize, cmd: &ast::Transform, reason: Reason| {
+            if let Some(report) = report.as_mut() {
+                report.skipped.push(Skipped {
+                    index,
+                    statement: cmd.to_string(),
+                    reason,
+                });
+            }
+        };
+        // transforms after running out of time are skipped
+        let mut timed_out = None;
+
+        let mut idx = 0;
+        for scene in self.scenes.iter_mut() {
+            // `t` counts from the start of the scene
+            let t = match scene.window {
+                None => t,
+                Some(w) if w.contains(position) => position - w.start,
+                Some(_) => {
+                    idx += scene.transforms.len();
+                    continue;
                 }
             };
 
-            let ops = shape_ops(idx.to_string(), cmd, detection);
-            if ops.len() > 0 {
-                output = transform.execute(gpu, &output, ops);
+            if timed_out.is_none() && !scene.active {
+                // animations start over each time a scene enters
+                for (transform, _) in scene.transforms.iter_mut() {
+                    transform.reset();
+                }
+                scene.active = true;
+            }
+
+            for (transform, cmd) in scene.transforms.iter_mut() {
+                if timed_out.is_none()
+                    && let Err(e) = timeout_check(&format!("Transform {idx}"))
+                {
+                    warn!("{e:?}");
+                    timed_out = Some(e.to_string());
+                }
+                if let Some(e) = &timed_out {
+                    skip(idx, cmd, Reason::Timeout(e.clone()));
+                    idx += 1;
+                    continue;
+                }
+
+                if cmd.operations.iter().any(|o| o.is_animated()) {
+                    apply_shape_agnostic_operations(transform, cmd, t, &self.params);
+                }
+
+                let frame = (output.width(), output.height());
+                if strict {
+                    for reason in skip_reasons(cmd, detection, frame) {
+                        skip(idx, cmd, reason);
+                    }
+                    for o in &cmd.operations {
+                        if let Operation::Lut { path, .. } = o
+                            && gpu.lut(path).is_none()
+                        {
+                            skip(idx, cmd, Reason::MissingLut(path.clone()));
+                        }
+                    }
+                }
+                // cache keys only need to be unique within a transform, so
+                // don't depend on its position, which can change on reload
+                let ops = shape_ops("op".to_string(), cmd, detection, frame);
+                if ops.len() > 0 {
+                    output = transform.execute(gpu, &output, ops);
+                }
+                idx += 1;
             }
         }
 
+        if strict {
+            self.report = report;
+        }
         output
     }
 }
 
-fn build_transform(cmd: &ast::Transform, gpu: &mut GpuExecutor) -> Transform {
+// Why a transform won't run as written on a frame
+fn skip_reasons(cmd: &ast::Transform, detection: &Detection, frame: (u32, u32)) -> Vec<Reason> {
+    let missing = lint::face_refs(cmd)
+        .into_iter()
+        .filter(|fr| match fr.face_idx {
+            _ if detection.is_empty() => true,
+            Some(ast::FaceIdx::Absolute(abs)) => abs as usize >= detection.len(),
+            _ => false,
+        });
+    let options = LintOptions {
+        frame: Some(frame),
+        max_faces: None,
+    };
+    let mut reasons = missing
+        .map(|fr| Reason::MissingFace(fr.to_string()))
+        .collect::<Vec<_>>();
+    reasons.extend(
+        lint::lint_transform(cmd, &options)
+            .into_iter()
+            .map(|(_, message)| Reason::Unsupported(message)),
+    );
+    reasons
+}
+
+// Looping timelines repeat once the last scene ends
+fn loop_period(windows: &[ast::Window]) -> Option<f32> {
+    windows
+        .iter()
+        .map(|w| w.end)
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .reduce(f32::max)
+}
+
+fn timeline_position(t: f32, loop_period: Option<f32>) -> f32 {
+    match loop_period {
+        Some(period) => t % period,
+        None => t,
+    }
+}
+
+// Mark the scenes the timeline left on its way from `last` to `position` as
+// inactive, so they start over when they next enter. A looping timeline
+// wrapping around leaves every scene, even one spanning the whole loop.
+fn leave_scenes(scenes: &mut [Scene], last: f32, position: f32) {
+    let wrapped = position < last;
+    for scene in scenes.iter_mut() {
+        if let Some(w) = scene.window
+            && (wrapped || !w.contains(position))
+        {
+            scene.active = false;
+        }
+    }
+}
+
+// The previous transform that's the same as `cmd` in the same scene,
+// wherever it's been moved to in the config
+fn take_unchanged<T>(
+    previous: &mut Vec<(Option<ast::Window>, (T, ast::Transform))>,
+    window: Option<ast::Window>,
+    cmd: &ast::Transform,
+) -> Option<(T, ast::Transform)> {
+    let i = previous
+        .iter()
+        .position(|(w, (_, c))| *w == window && c.same_ignoring_span(cmd))?;
+    Some(previous.swap_remove(i).1)
+}
+
+fn build_transform(
+    cmd: &ast::Transform,
+    gpu: &mut GpuExecutor,
+    params: &HashMap<String, f32>,
+) -> Transform {
     let mut t = Transform::new(gpu);
-    apply_shape_agnostic_operations(&mut t, cmd);
+    apply_shape_agnostic_operations(&mut t, cmd, 0., params);
     t
 }
 
-fn apply_shape_agnostic_operations(t: &mut Transform, cmd: &ast::Transform) {
-    for o in &cmd.operations {
+// Operation arguments are evaluated at `time` seconds with the current
+// params, so this is re-run every frame for transforms with animated
+// arguments
+fn apply_shape_agnostic_operations(
+    t: &mut Transform,
+    cmd: &ast::Transform,
+    time: f32,
+    params: &HashMap<String, f32>,
+) {
+    let eval = |e: &ast::Expr| e.eval_with(time, params);
+    let mut settings = Settings::default();
+    configure(&mut settings, &cmd.operations, cmd.ordered, &eval);
+
+    // copies with operations of their own start from the transform's
+    // settings, numbered the same way as in `shape_ops_for_src_shape`
+    let mut destinations = HashMap::new();
+    let targets = cmd.operations.iter().flat_map(|o| match o {
+        Operation::CopyTo(targets) => targets.as_slice(),
+        _ => &[],
+    });
+    for (idx, target) in targets.enumerate() {
+        if !target.operations.is_empty() {
+            let mut dest = settings.clone();
+            configure(&mut dest, &target.operations, cmd.ordered, &eval);
+            destinations.insert(idx, dest);
+        }
+    }
+
+    t.configure(settings, destinations);
+}
+
+// Apply `ops` on top of `s`. Ordered transforms add geometric operations as
+// steps after those `s` already has.
+fn configure(
+    s: &mut Settings,
+    ops: &[Operation],
+    ordered: bool,
+    eval: &impl Fn(&ast::Expr) -> f32,
+) {
+    if ordered {
+        let mut steps = Vec::new();
+        for o in ops {
+            match o {
+                Operation::Translate(x, y) => steps.push(Step::Translate(eval(x), eval(y))),
+                Operation::Scale(f) => steps.push(Step::Scale(eval(f))),
+                Operation::Rotate(r) => steps.push(Step::Rotate(eval(r))),
+                Operation::Flip(v) => steps.push(Step::Flip(*v)),
+                Operation::Spin(velocity) => {
+                    s.set_spin(eval(velocity));
+                    steps.push(Step::Spin);
+                }
+                Operation::Drift(velocity, angle) => {
+                    s.set_drift(eval(velocity), eval(angle));
+                    steps.push(Step::Drift);
+                }
+                _ => {}
+            }
+        }
+        s.add_steps(steps);
+    }
+
+    let colour = ops
+        .iter()
+        .filter_map(|o| match o {
+            Operation::Hue(degrees) => Some(ColourStep::Hue(eval(degrees))),
+            Operation::Contrast(f) => Some(ColourStep::Contrast(eval(f))),
+            Operation::Gamma(f) => Some(ColourStep::Gamma(eval(f))),
+            Operation::Invert => Some(ColourStep::Invert),
+            Operation::Grayscale => Some(ColourStep::Grayscale),
+            Operation::Sepia(f) => Some(ColourStep::Sepia(eval(f))),
+            _ => None,
+        })
+        .collect();
+    s.add_colour_steps(colour);
+
+    for o in ops {
         match o {
-            Operation::Brightness(b) => t.set_brightness(*b),
-            Operation::Chans(r, g, b) => t.set_chans(*r, *g, *b),
-            Operation::Reshape(dxl, dxr, dyt, dyb) => t.set_reshape(*dxl, *dxr, *dyt, *dyb),
-            Operation::Drift(velocity, angle) => t.set_drift(*velocity, *angle),
-            Operation::Flip(v) => t.set_flip(*v),
-            Operation::Rotate(r) => t.set_rot_degrees(*r),
-            Operation::Saturation(s) => t.set_saturation(*s),
-            Operation::Scale(s) => t.set_scale(*s),
-            Operation::Spin(velocity) => t.set_spin(*velocity),
-            Operation::Tile => t.set_tiling(true),
-            Operation::Translate(x, y) => t.translate_by(*x, *y),
+            // already applied as steps
+            Operation::Translate(..)
+            | Operation::Scale(_)
+            | Operation::Rotate(_)
+            | Operation::Flip(_)
+            | Operation::Spin(_)
+            | Operation::Drift(..)
+                if ordered => {}
+            Operation::Blur(r) => s.set_blur(eval(r)),
+            Operation::Lut { path, strength } => s.set_lut(path, eval(strength)),
+            Operation::Pixelate(size, mode) => s.set_pixelate(eval(size), *mode),
+            Operation::Opacity(f) => s.set_opacity(eval(f)),
+            Operation::Blend(mode) => s.set_blend(*mode),
+            Operation::Brightness(b) => s.set_brightness(eval(b)),
+            Operation::Chans(r, g, b) => s.set_chans(eval(r), eval(g), eval(b)),
+            Operation::Reshape(dxl, dxr, dyt, dyb) => {
+                s.set_reshape(eval(dxl), eval(dxr), eval(dyt), eval(dyb))
+            }
+            Operation::Drift(velocity, angle) => s.set_drift(eval(velocity), eval(angle)),
+            Operation::Flip(v) => s.set_flip(*v),
+            Operation::Rotate(r) => s.set_rot_degrees(eval(r)),
+            Operation::Saturation(f) => s.set_saturation(eval(f)),
+            Operation::Scale(f) => s.set_scale(eval(f)),
+            Operation::Spin(velocity) => s.set_spin(eval(velocity)),
+            Operation::Tile => s.set_tiling(true),
+            Operation::Translate(x, y) => {
+                s.translate_by(eval(x).round() as i32, eval(y).round() as i32)
+            }
             _ => {}
         }
     }
 }
 
+// `frame` is the (width, height) of the image being transformed, for
+// shapes positioned relative to it
 fn shape_ops(
     cache_key_prefix: String,
     cmd: &ast::Transform,
     detection: &Detection,
+    frame: (u32, u32),
 ) -> Vec<ShapeOp> {
-    match &cmd.shape {
-        ast::Shape::Rect(r) => shape_ops_for_src_shape(
+    shape_ops_for_shape(cache_key_prefix, &cmd.shape, cmd, detection, frame)
+}
+
+// `shape` is the transform's own shape, or one of them if it's a union
+fn shape_ops_for_shape(
+    cache_key_prefix: String,
+    shape: &ast::Shape,
+    cmd: &ast::Transform,
+    detection: &Detection,
+    frame: (u32, u32),
+) -> Vec<ShapeOp> {
+    // conditions are checked against the face whose part is transformed
+    let applies = |idx: Option<usize>| {
+        cmd.condition
+            .as_ref()
+            .is_none_or(|c| condition_holds(c, detection, idx, frame))
+    };
+    // a transform of one particular face's part
+    let on_face = |fr: &ast::FaceRef, idx: Option<usize>| match idx
+        .and_then(|idx| Some((idx, detection.get(idx)?)))
+    {
+        Some((idx, _)) if !applies(Some(idx)) => Vec::new(),
+        Some((idx, face)) => shape_ops_for_src_shape(
+            cache_key_prefix.clone(),
+            part_shape(fr, face),
+            &cmd.operations,
+            detection,
+            frame,
+            Some(idx),
+        ),
+        None => {
+            warn!("No matching face found for {cmd:?}");
+            Vec::new()
+        }
+    };
+
+    match shape {
+        ast::Shape::Frame(_) if !applies(None) => Vec::new(),
+        ast::Shape::Frame(fs) => shape_ops_for_src_shape(
             cache_key_prefix,
-            r.clone(),
+            fs.resolve(frame),
             &cmd.operations,
             detection,
+            frame,
             None,
         ),
-        ast::Shape::FaceRef(fr) => match fr.face_idx {
-            Some(ast::FaceIdx::Absolute(abs)) => match detection.get(abs as usize) {
-                Some(face) => shape_ops_for_src_shape(
-                    cache_key_prefix,
-                    face_shape(&fr.part, face),
-                    &cmd.operations,
-                    detection,
-                    Some(abs as usize),
-                ),
-                None => {
-                    warn!("No matching face found for {cmd:?}");
-                    Vec::new()
-                }
-            },
+        ast::Shape::FaceRef(fr) => match &fr.face_idx {
+            Some(ast::FaceIdx::Absolute(abs)) => on_face(fr, Some(*abs as usize)),
+            Some(ast::FaceIdx::Select(s)) => on_face(fr, select(s, detection, frame)),
             Some(ast::FaceIdx::Relative(rel)) => {
                 let mut ops = Vec::new();
                 for (idx, face) in detection.iter().enumerate() {
+                    if !applies(Some(idx)) {
+                        continue;
+                    }
+
                     ops.append(&mut shape_ops_for_src_shape(
                         format!("{cache_key_prefix}-{idx}"),
-                        face_shape(&fr.part, face),
+                        part_shape(fr, face),
                         &cmd.operations,
                         detection,
-                        Some(((idx as i32 - rel) % detection.len() as i32) as usize),
+                        frame,
+                        Some(wrap_face_idx(idx as i32 - rel, detection.len())),
                     ));
                 }
                 ops
@@ -135,17 +756,35 @@ fn shape_ops(
             None => {
                 let mut ops = Vec::new();
                 for (idx, face) in detection.iter().enumerate() {
+                    if !applies(Some(idx)) {
+                        continue;
+                    }
+
                     ops.append(&mut shape_ops_for_src_shape(
                         format!("{cache_key_prefix}-{idx}"),
-                        face_shape(&fr.part, face),
+                        part_shape(fr, face),
                         &cmd.operations,
                         detection,
+                        frame,
                         Some(idx),
                     ));
                 }
                 ops
             }
         },
+        ast::Shape::Union(members) => members
+            .iter()
+            .enumerate()
+            .flat_map(|(i, member)| {
+                shape_ops_for_shape(
+                    format!("{cache_key_prefix}-u{i}"),
+                    member,
+                    cmd,
+                    detection,
+                    frame,
+                )
+            })
+            .collect(),
     }
 }
 
@@ -154,50 +793,53 @@ fn shape_ops_for_src_shape(
     src: impl Into<Shape> + Clone,
     ops: &Vec<ast::Operation>,
     detection: &Detection,
+    frame: (u32, u32),
     target_face_idx: Option<usize>,
 ) -> Vec<ShapeOp> {
     let mut sops = Vec::new();
+    let shapes = |shape: &ast::Shape| shapes(shape, detection, target_face_idx, frame);
 
-    for o in ops {
+    // destinations are numbered across every `copy_to` in the transform
+    let mut idx = 0;
+    for (oidx, o) in ops.iter().enumerate() {
         match o {
-            Operation::CopyTo(others) => {
-                for (idx, other) in others.iter().enumerate() {
-                    match other {
-                        ast::Shape::FaceRef(fr) => {
-                            for (sidx, s) in
-                                shapes(&fr, detection, target_face_idx).iter().enumerate()
-                            {
-                                sops.push(ShapeOp::copy(
-                                    format!("{cache_key_prefix}-{idx}-{sidx}"),
-                                    src.clone(),
-                                    s.clone(),
-                                ));
-                            }
-                        }
-                        ast::Shape::Rect(r) => sops.push(ShapeOp::copy(
-                            format!("{cache_key_prefix}-{idx}"),
+            Operation::CopyTo(targets) => {
+                for target in targets {
+                    for (sidx, s) in shapes(&target.shape).into_iter().enumerate() {
+                        sops.push(ShapeOp::copy(
+                            format!("{cache_key_prefix}-{idx}-{sidx}"),
+                            idx,
                             src.clone(),
-                            r.clone(),
-                        )),
+                            s,
+                        ));
                     }
+                    idx += 1;
                 }
             }
-            Operation::SwapWith(other) => match other {
-                ast::Shape::FaceRef(fr) => {
-                    for (sidx, s) in shapes(&fr, detection, target_face_idx).iter().enumerate() {
-                        sops.push(ShapeOp::swap(
-                            format!("{cache_key_prefix}-{sidx}"),
-                            src.clone(),
-                            s.clone(),
-                        ));
-                    }
+            Operation::SwapWith(other) => {
+                for (sidx, s) in shapes(other).into_iter().enumerate() {
+                    sops.push(ShapeOp::swap(
+                        format!("{cache_key_prefix}-swap-{oidx}-{sidx}"),
+                        src.clone(),
+                        s,
+                    ));
+                }
+            }
+            Operation::Cycle {
+                shapes: others,
+                period,
+            } => {
+                // shapes without a matching face drop out of the cycle
+                let others = others.iter().flat_map(shapes).collect::<Vec<_>>();
+                if !others.is_empty() {
+                    sops.push(ShapeOp::cycle(
+                        format!("{cache_key_prefix}-cycle-{oidx}"),
+                        src.clone(),
+                        others,
+                        *period,
+                    ));
                 }
-                ast::Shape::Rect(r) => sops.push(ShapeOp::swap(
-                    format!("{cache_key_prefix}-rect"),
-                    src.clone(),
-                    r.clone(),
-                )),
-            },
+            }
             _ => {}
         }
     }
@@ -209,33 +851,123 @@ fn shape_ops_for_src_shape(
     sops
 }
 
-fn shapes(fr: &ast::FaceRef, d: &Detection, target_idx: Option<usize>) -> Vec<Shape> {
-    match fr.face_idx {
-        Some(ast::FaceIdx::Absolute(abs)) => match d.get(abs as usize) {
-            Some(face) => Vec::from([face_shape(&fr.part, face)]),
-            None => Vec::new(),
-        },
+// Every shape a reference points to, relative to the face at `target_idx`
+fn shapes(
+    shape: &ast::Shape,
+    d: &Detection,
+    target_idx: Option<usize>,
+    frame: (u32, u32),
+) -> Vec<Shape> {
+    match shape {
+        ast::Shape::FaceRef(fr) => faces(fr, d, target_idx, frame)
+            .into_iter()
+            .map(|face| part_shape(fr, face))
+            .collect(),
+        ast::Shape::Frame(fs) => Vec::from([fs.resolve(frame)]),
+        ast::Shape::Union(members) => members
+            .iter()
+            .flat_map(|member| shapes(member, d, target_idx, frame))
+            .collect(),
+    }
+}
+
+fn condition_holds(
+    c: &ast::Condition,
+    d: &Detection,
+    target_idx: Option<usize>,
+    frame: (u32, u32),
+) -> bool {
+    faces(&c.metric.face, d, target_idx, frame)
+        .into_iter()
+        .any(|face| c.holds(metric_value(&c.metric, face)))
+}
+
+fn metric_value(m: &ast::Metric, f: &Face) -> f32 {
+    match (m.kind, &m.face.part) {
+        (ast::MetricKind::MouthOpen, _) => f.mouth_open,
+        (ast::MetricKind::EyeOpen, ast::FacePart::LEye | ast::FacePart::LEyeRegion) => f.l_eye_open,
+        (ast::MetricKind::EyeOpen, ast::FacePart::REye | ast::FacePart::REyeRegion) => f.r_eye_open,
+        (ast::MetricKind::EyeOpen, _) => (f.l_eye_open + f.r_eye_open) / 2.,
+    }
+}
+
+// Faces a reference points to, relative to the face at `target_idx`
+fn faces<'a>(
+    fr: &ast::FaceRef,
+    d: &'a Detection,
+    target_idx: Option<usize>,
+    frame: (u32, u32),
+) -> Vec<&'a Face> {
+    match &fr.face_idx {
+        Some(ast::FaceIdx::Absolute(abs)) => d.get(*abs as usize).into_iter().collect(),
+        Some(ast::FaceIdx::Select(s)) => {
+            select(s, d, frame).map(|idx| &d[idx]).into_iter().collect()
+        }
         Some(ast::FaceIdx::Relative(rel)) => {
-            let idx = (match target_idx {
-                Some(target_idx) => rel + target_idx as i32,
-                None => rel,
-            } % d.len() as i32) as usize;
+            if d.is_empty() {
+                return Vec::new();
+            }
+
+            let idx = wrap_face_idx(
+                match target_idx {
+                    Some(target_idx) => rel + target_idx as i32,
+                    None => *rel,
+                },
+                d.len(),
+            );
 
-            Vec::from([face_shape(&fr.part, &d[idx])])
+            Vec::from([&d[idx]])
         }
         None => match target_idx {
-            Some(target_idx) => match d.get(target_idx) {
-                Some(face) => Vec::from([face_shape(&fr.part, face)]),
-                None => Vec::new(),
-            },
-            None => d
-                .iter()
-                .map(|face| face_shape(&fr.part, face))
-                .collect::<Vec<_>>(),
+            Some(target_idx) => d.get(target_idx).into_iter().collect(),
+            None => d.iter().collect(),
         },
     }
 }
 
+// Index of the face a selector picks, if any were detected
+fn select(s: &ast::Selector, d: &Detection, (width, height): (u32, u32)) -> Option<usize> {
+    let center = |f: &Face| {
+        let c = f.bound.center();
+        (c.x as f32, c.y as f32)
+    };
+    // the face with the lowest key wins
+    let key = |f: &Face| match s {
+        ast::Selector::Largest => -(f.bound.area() as f32),
+        ast::Selector::Smallest => f.bound.area() as f32,
+        ast::Selector::Leftmost => center(f).0,
+        ast::Selector::Rightmost => -center(f).0,
+        ast::Selector::Nearest(x, y) => {
+            let (cx, cy) = center(f);
+            (cx - x * width as f32).hypot(cy - y * height as f32)
+        }
+    };
+    d.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| key(a).total_cmp(&key(b)))
+        .map(|(idx, _)| idx)
+}
+
+// Relative indices can point before the first or past the last face,
+// wrap them around so they always land on a detected face
+fn wrap_face_idx(idx: i32, face_count: usize) -> usize {
+    idx.rem_euclid(face_count as i32) as usize
+}
+
+// Shape of a face part, moved or resized by any adjustments
+fn part_shape(fr: &ast::FaceRef, f: &Face) -> Shape {
+    let shape = face_shape(&fr.part, f);
+    if fr.adjustments.is_empty() {
+        return shape;
+    }
+
+    let mut poly = Polygon::from(shape);
+    for a in &fr.adjustments {
+        a.apply(&mut poly, f.rot);
+    }
+    poly.into()
+}
+
 fn face_shape(p: &ast::FacePart, f: &Face) -> Shape {
     match p {
         ast::FacePart::LEye => f.l_eye.clone(),
@@ -254,7 +986,7 @@ fn face_shape(p: &ast::FacePart, f: &Face) -> Shape {
 fn parse_test() -> Result<()> {
     let stuff = r#"leye: translate(100, -80)
     mouth#1: swap_with(mouth#0)
-    mouth#0: scale(2.5), write_to(leye_region, nose), swap_with(reye)
+    mouth#0: scale(2.5), copy_to(leye_region, nose), swap_with(reye)
     "#;
 
     let res = parser::StatementsParser::new().parse(&stuff)?;
@@ -264,7 +996,1288 @@ fn parse_test() -> Result<()> {
 
 #[test]
 fn write_to_multiple() -> Result<()> {
-    let stmt = "mouth#0: scale(2.5), write_to(leye_region, nose), swap_with(reye)";
+    let stmt = "mouth#0: scale(2.5), copy_to(leye_region, nose), swap_with(reye)";
     let _res = parser::StatementParser::new().parse(&stmt)?;
     Ok(())
 }
+
+#[test]
+fn copy_to_destination_operations() -> Result<()> {
+    let stmts = parse_statements(
+        "let small = scale(0.5), spin\nmouth#0: scale(2), copy_to(leye { small }, nose { flip(horizontal), brighten(1.5) }, reye), copy_to(rect(0, 0, 10, 10) { rotate(t) })",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    let targets = t
+        .operations
+        .iter()
+        .flat_map(|o| match o {
+            Operation::CopyTo(targets) => targets.iter().map(|t| t.operations.len()).collect(),
+            _ => Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(targets, [2, 2, 0, 1]);
+    assert!(t.operations[2].is_animated());
+
+    // destinations are numbered across every copy_to
+    let detection = test_detection(1);
+    let destinations = shape_ops("0".to_string(), t, &detection, TEST_FRAME)
+        .iter()
+        .map(|op| op.destination)
+        .collect::<Vec<_>>();
+    assert_eq!(destinations, [Some(0), Some(1), Some(2), Some(3)]);
+
+    let err = parse_statements("face: copy_to(leye { tile })").unwrap_err();
+    assert_eq!(err.message, "unexpected token `tile`");
+
+    let err =
+        parse_statements("let pop = scale(2), tile\nface: copy_to(leye { pop })").unwrap_err();
+    assert_eq!(
+        err.message,
+        "`pop` can't be used on a copy_to destination, as it includes `tile`"
+    );
+    assert_eq!((err.line, err.column), (2, 22));
+    Ok(())
+}
+
+#[test]
+fn blur_operation() -> Result<()> {
+    let stmts = parse_statements("face#1: blur(12)\nmouth: copy_to(nose { blur(2 * 3) })")?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    assert_eq!(t.operations, [Operation::Blur(ast::Expr::Num(12.))]);
+    let ast::Statement::Transform(t) = &stmts[1] else {
+        panic!("Expected transform")
+    };
+    let Operation::CopyTo(targets) = &t.operations[0] else {
+        panic!("Expected copy_to")
+    };
+    assert_eq!(targets[0].operations[0].keyword(), "blur");
+    Ok(())
+}
+
+#[test]
+fn pixelate_operation() -> Result<()> {
+    let stmts = parse_statements(
+        "let hex = 1\nmouth: pixelate(8), swap_with(leye)\nface: pixelate(hex * 12, hex)",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    assert_eq!(
+        t.operations[0],
+        Operation::Pixelate(ast::Expr::Num(8.), ast::PixelateMode::Grid)
+    );
+    let ast::Statement::Transform(t) = &stmts[1] else {
+        panic!("Expected transform")
+    };
+    assert!(matches!(
+        t.operations[0],
+        Operation::Pixelate(_, ast::PixelateMode::Hex)
+    ));
+
+    let err = parse_statements("face: pixelate(8, hexagon)").unwrap_err();
+    assert_eq!(err.message, "unknown pixelate mode, expected grid or hex");
+    assert_eq!((err.line, err.column), (1, 19));
+    Ok(())
+}
+
+#[test]
+fn lut_operation() -> Result<()> {
+    let stmts = parse_statements(
+        "face: lut(\"film.cube\")\nat 0s..1s { mouth: lut(\"film.cube\", 0.5)\nleye: lut(\"luts/teal.cube\") }",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    assert_eq!(
+        t.operations,
+        [Operation::Lut {
+            path: "film.cube".to_string(),
+            strength: ast::Expr::Num(1.),
+        }]
+    );
+    assert_eq!(lut_paths(&stmts), ["film.cube", "luts/teal.cube"]);
+
+    let err = parse_statements("face: copy_to(leye { lut(\"film.cube\") })").unwrap_err();
+    assert_eq!(err.message, "unexpected token `lut(`");
+    Ok(())
+}
+
+#[test]
+fn opacity_and_blend_operations() -> Result<()> {
+    let stmts = parse_statements(
+        "mouth: copy_to(leye { opacity(0.5), blend(multiply) }, reye)\nface: blend(difference)",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    let Operation::CopyTo(targets) = &t.operations[0] else {
+        panic!("Expected copy_to")
+    };
+    assert_eq!(
+        targets[0].operations,
+        [
+            Operation::Opacity(ast::Expr::Num(0.5)),
+            Operation::Blend(ast::BlendMode::Multiply)
+        ]
+    );
+    let ast::Statement::Transform(t) = &stmts[1] else {
+        panic!("Expected transform")
+    };
+    assert_eq!(t.operations, [Operation::Blend(ast::BlendMode::Difference)]);
+
+    let err = parse_statements("face: blend(darken)").unwrap_err();
+    assert_eq!(
+        err.message,
+        "unknown blend mode, expected normal, multiply, screen, overlay, add or difference"
+    );
+    assert_eq!((err.line, err.column), (1, 13));
+    Ok(())
+}
+
+#[test]
+fn cycle_shapes() -> Result<()> {
+    let stmts = parse_statements(
+        "leye: cycle(reye, mouth, nose)\nmouth#0: cycle(mouth#1, mouth#2, period=1500ms)",
+    )?;
+    let cycles = stmts
+        .iter()
+        .map(|s| match s {
+            ast::Statement::Transform(t) => t,
+            _ => panic!("Expected transform"),
+        })
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        &cycles[1].operations[0],
+        Operation::Cycle { shapes, period: Some(1.5) } if shapes.len() == 2
+    ));
+
+    // one op per face, cycling through the parts of that face
+    let detection = test_detection(2);
+    let ops = shape_ops("0".to_string(), cycles[0], &detection, TEST_FRAME);
+    assert_eq!(ops.len(), 2);
+    assert_eq!(ops[0].cycle.len(), 3);
+    assert_eq!(ops[0].period, None);
+
+    // faces that aren't detected drop out
+    let ops = shape_ops("0".to_string(), cycles[1], &detection, TEST_FRAME);
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].cycle.len(), 1);
+    assert_eq!(ops[0].period, Some(1.5));
+
+    for (src, message) in [
+        ("face: cycle()", "cycle needs at least one shape"),
+        (
+            "face: cycle(period=2s, nose)",
+            "cycle period must come after its shapes",
+        ),
+        (
+            "face: cycle(nose, period=0s)",
+            "cycle period must be longer than 0s",
+        ),
+        ("face: cycle(nose, every=2s)", "cycle only takes a `period`"),
+    ] {
+        assert_eq!(parse_statements(src).unwrap_err().message, message, "{src}");
+    }
+
+    let err = statements_from_json(
+        r#"[{"transform": {"shape": {"face_ref": {"part": "face"}}, "operations": [{"cycle": {"shapes": [{"face_ref": {"part": "nose"}}], "period": 0.0}}]}}]"#,
+    )
+    .unwrap_err();
+    assert_eq!(err.message, "cycle period must be longer than 0s");
+    Ok(())
+}
+
+#[test]
+fn union_shapes() -> Result<()> {
+    let detection = test_detection(2);
+    let stmt = parser::StatementParser::new()
+        .parse("leye|reye.grow(2): copy_to(mouth|rect(0, 0, 10, 10))")?;
+    let ast::Statement::Transform(t) = stmt else {
+        panic!("Expected transform")
+    };
+    // each eye of each face, copied to each destination
+    let ops = shape_ops("0".to_string(), &t, &detection, TEST_FRAME);
+    assert_eq!(ops.len(), 8);
+    assert_eq!(ops[0].destination, ops[1].destination);
+    let mut ids = ops.iter().map(|op| op.id.as_str()).collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 8);
+    Ok(())
+}
+
+#[test]
+fn face_selectors() -> Result<()> {
+    use crate::shapes::rect::Rect;
+
+    let mut detection = test_detection(3);
+    detection[1].bound = Rect::from_tl(200, 0, 300, 300);
+    let selected = |src: &str| {
+        let stmt = parser::StatementParser::new().parse(src).unwrap();
+        let ast::Statement::Transform(t) = stmt else {
+            panic!("Expected transform")
+        };
+        let ops = shape_ops("0".to_string(), &t, &detection, TEST_FRAME);
+        assert_eq!(ops.len(), 1, "{src}");
+        Rect::from(ops[0].base.clone()).left() / 200
+    };
+
+    assert_eq!(selected("face@largest: spin"), 1);
+    // ties go to the first face detected
+    assert_eq!(selected("face@smallest: spin"), 0);
+    assert_eq!(selected("face@leftmost: spin"), 0);
+    assert_eq!(selected("face@rightmost: spin"), 2);
+    assert_eq!(selected("face@nearest(0.9, 0.1): spin"), 2);
+
+    // targets pick the same face whichever face the source is on
+    let stmt = parser::StatementParser::new().parse("mouth: swap_with(nose@leftmost)")?;
+    let ast::Statement::Transform(t) = stmt else {
+        panic!("Expected transform")
+    };
+    let ops = shape_ops("0".to_string(), &t, &detection, TEST_FRAME);
+    assert_eq!(ops.len(), 3);
+    for op in ops {
+        assert!(Rect::from(op.swap.unwrap()).left() < 200);
+    }
+
+    let err = parse_statements("face@biggest: spin").unwrap_err();
+    assert_eq!(
+        err.message,
+        "unknown selector, expected largest, smallest, leftmost, rightmost or nearest(x, y)"
+    );
+    Ok(())
+}
+
+#[test]
+fn strict_skip_reasons() -> Result<()> {
+    let detection = test_detection(2);
+    let reasons = |src: &str, detection: &Detection| {
+        let stmt = parser::StatementParser::new().parse(src).unwrap();
+        let ast::Statement::Transform(t) = stmt else {
+            panic!("Expected transform")
+        };
+        skip_reasons(&t, detection, TEST_FRAME)
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert!(reasons("mouth#1: copy_to(nose@largest)", &detection).is_empty());
+    assert_eq!(
+        reasons("mouth#2: copy_to(nose)", &detection),
+        ["no detected face matches `mouth#2`"]
+    );
+    assert_eq!(
+        reasons("mouth: spin", &Vec::new()),
+        ["no detected face matches `mouth`"]
+    );
+    assert_eq!(
+        reasons("leye: tile, rotate(45)", &detection),
+        ["`rotate` has no effect on tiled shapes"]
+    );
+    assert_eq!(
+        reasons("rect(700, 0, 10, 10): spin", &detection),
+        ["`rect(700, 0, 10, 10)` is entirely outside the 640x480 frame"]
+    );
+
+    let skipped = Skipped {
+        index: 3,
+        statement: "mouth#2: spin(0.5)".to_string(),
+        reason: Reason::Timeout("40ms exceeds allowed time of 30ms".to_string()),
+    };
+    assert_eq!(
+        skipped.to_string(),
+        "transform 3 `mouth#2: spin(0.5)`: timed out: 40ms exceeds allowed time of 30ms"
+    );
+    assert_eq!(skipped.reason.kind(), "timeout");
+    Ok(())
+}
+
+#[cfg(test)]
+const TEST_FRAME: (u32, u32) = (640, 480);
+
+#[cfg(test)]
+fn test_detection(face_count: u32) -> Detection {
+    use crate::shapes::rect::Rect;
+
+    (0..face_count)
+        .map(|i| {
+            let part = |x: u32, y: u32| Polygon::from(Rect::from_tl(i * 200 + x, y, 20, 20));
+            Face {
+                face: part(0, 0),
+                mouth: part(80, 140),
+                nose: part(90, 100),
+                l_eye: part(40, 60),
+                l_eye_region: part(40, 40),
+                r_eye: part(140, 60),
+                r_eye_region: part(140, 40),
+                bound: Rect::from_tl(i * 200, 0, 200, 200),
+                forehead: part(80, 10),
+                mouth_open: 0.,
+                l_eye_open: 0.3,
+                r_eye_open: 0.3,
+                rot: 0.,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn negative_relative_face_idx() -> Result<()> {
+    let stmts =
+        parser::StatementsParser::new().parse("mouth-1: copy_to(mouth)\nleye: copy_to(leye-2)")?;
+    assert_eq!(stmts.len(), 2);
+
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.shape {
+        ast::Shape::FaceRef(fr) => {
+            assert!(matches!(fr.face_idx, Some(ast::FaceIdx::Relative(-1))))
+        }
+        s => panic!("Unexpected shape {s:?}"),
+    }
+
+    let ast::Statement::Transform(t) = &stmts[1] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[0] {
+        Operation::CopyTo(dests) => match &dests[0].shape {
+            ast::Shape::FaceRef(fr) => {
+                assert!(matches!(fr.face_idx, Some(ast::FaceIdx::Relative(-2))))
+            }
+            s => panic!("Unexpected shape {s:?}"),
+        },
+        o => panic!("Unexpected operation {o:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn negative_numbers_still_parse() -> Result<()> {
+    let stmt = parse_statements("face: rotate(-45), translate(-10, -2.5)");
+    assert!(stmt.is_err(), "translate only accepts integers");
+
+    let ast::Statement::Transform(t) =
+        parser::StatementParser::new().parse("face: rotate(-45), translate(-10, -20)")?
+    else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        [Operation::Rotate(r), Operation::Translate(x, y)] => {
+            assert_eq!(r.value(), -45.);
+            assert_eq!((x.value(), y.value()), (-10., -20.));
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn negative_relative_src_wraps() -> Result<()> {
+    let detection = test_detection(3);
+    let ast::Statement::Transform(t) =
+        parser::StatementParser::new().parse("mouth-1: copy_to(mouth)")?
+    else {
+        panic!("Expected transform")
+    };
+    let ops = shape_ops("0".to_string(), &t, &detection, TEST_FRAME);
+
+    assert_eq!(ops.len(), 3);
+    for (idx, op) in ops.iter().enumerate() {
+        assert_eq!(op.base.center(), detection[idx].mouth.center());
+        // each mouth is copied to the "next" mouth, wrapping from last to first
+        assert_eq!(
+            op.dest.as_ref().unwrap().center(),
+            detection[(idx + 1) % 3].mouth.center()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn negative_relative_dest_wraps() -> Result<()> {
+    let detection = test_detection(3);
+    let ast::Statement::Transform(t) =
+        parser::StatementParser::new().parse("leye: copy_to(leye-2)")?
+    else {
+        panic!("Expected transform")
+    };
+    let ops = shape_ops("0".to_string(), &t, &detection, TEST_FRAME);
+
+    assert_eq!(ops.len(), 3);
+    for (idx, op) in ops.iter().enumerate() {
+        assert_eq!(op.base.center(), detection[idx].l_eye.center());
+        assert_eq!(
+            op.dest.as_ref().unwrap().center(),
+            detection[(idx + 1) % 3].l_eye.center()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn relative_idx_on_rect_without_faces() {
+    let t = ast::Transform {
+        shape: ast::Shape::Frame(ast::FrameShape::Rect(
+            ast::Length::Px(0),
+            ast::Length::Px(0),
+            ast::Length::Px(10),
+            ast::Length::Px(10),
+        )),
+        operations: Vec::from([Operation::SwapWith(ast::Shape::FaceRef(ast::FaceRef {
+            part: ast::FacePart::Nose,
+            face_idx: Some(ast::FaceIdx::Relative(-1)),
+            adjustments: Vec::new(),
+        }))]),
+        condition: None,
+        ordered: false,
+        span: ast::Span::default(),
+    };
+    let ops = shape_ops("0".to_string(), &t, &Vec::new(), TEST_FRAME);
+
+    // no faces to swap with, so only the rect itself is drawn
+    assert_eq!(ops.len(), 1);
+    assert!(ops[0].swap.is_none());
+}
+
+#[test]
+fn parse_error_location() {
+    let err = parse_ast("leye: scale(2)\nmouth: spin(0.5) brighten(2)").unwrap_err();
+    assert_eq!(err.line, 2);
+    assert_eq!(err.column, 18);
+    assert_eq!(err.token.as_deref(), Some("brighten("));
+    assert!(err.expected.contains(&"mouth".to_string()));
+}
+
+#[test]
+fn parse_error_suggests_operation() {
+    let err = parse_ast("mouth: scael(2)").unwrap_err();
+    assert_eq!((err.line, err.column), (1, 8));
+    assert_eq!(err.token.as_deref(), Some("scael"));
+    assert_eq!(err.suggestion.as_deref(), Some("scale"));
+
+    let rendered = err.render();
+    assert!(rendered.contains("1 | mouth: scael(2)\n  |        ^^^^^\n"));
+    assert!(rendered.contains("did you mean `scale`?"));
+}
+
+#[test]
+fn parse_error_suggests_face_part() {
+    let err = parse_ast("mouht: spin").unwrap_err();
+    assert_eq!(err.suggestion.as_deref(), Some("mouth"));
+
+    let err = parse_ast("mouth: copy_to(leey)").unwrap_err();
+    assert_eq!(err.suggestion.as_deref(), Some("leye"));
+}
+
+#[test]
+fn let_operation_group() -> Result<()> {
+    let stmts =
+        parse_statements("let pop = scale(1.5), spin(2)\nleye: pop, brighten(1.2)\nreye: pop")?;
+    assert_eq!(stmts.len(), 2);
+
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        [
+            Operation::Scale(s),
+            Operation::Spin(v),
+            Operation::Brightness(b),
+        ] => {
+            assert_eq!((s.value(), v.value(), b.value()), (1.5, 2., 1.2));
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn let_numeric_constant() -> Result<()> {
+    let stmts = parse_statements(
+        "let speed = 0.5\nlet fast = speed\nlet shift = 20\nface: spin(fast), translate(-shift, shift / 2)",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        [Operation::Spin(v), Operation::Translate(x, y)] => {
+            assert_eq!((v.value(), x.value(), y.value()), (0.5, -20., 10.));
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn let_errors() {
+    let err = parse_statements("let pop = scale(2)\nleye: pops").unwrap_err();
+    assert_eq!((err.line, err.column), (2, 7));
+    assert_eq!(err.suggestion.as_deref(), Some("pop"));
+
+    let err = parse_statements("let a = b, tile\nlet b = a\nface: a").unwrap_err();
+    assert!(err.message.contains("a -> b -> a"), "{}", err.message);
+
+    let err = parse_statements("let big = 2\nface: big").unwrap_err();
+    assert_eq!(err.message, "expected an operation but `big` is a number");
+
+    let err = parse_statements("let big = 2\nlet big = 3").unwrap_err();
+    assert_eq!((err.line, err.column), (2, 5));
+}
+
+#[test]
+fn statements_on_separate_lines() -> Result<()> {
+    let err = parse_statements("face: spin leye: scale(2)").unwrap_err();
+    assert_eq!(err.message, "expected a new line before this statement");
+    assert_eq!((err.line, err.column), (1, 12));
+
+    let err = parse_statements("let pop = scale(2) face: pop").unwrap_err();
+    assert_eq!((err.line, err.column), (1, 20));
+
+    let err = parse_statements("at 0s..5s { mouth: scale(2) face: spin }").unwrap_err();
+    assert_eq!((err.line, err.column), (1, 29));
+
+    // comments and blank lines between statements are fine
+    let stmts = parse_statements(
+        "face: spin // spins\n\nleye: scale(2) /* big */\nat 0s.. { mouth: spin }",
+    )?;
+    assert_eq!(stmts.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn expression_precedence() -> Result<()> {
+    let stmts = parse_statements("face: scale(1 + 2 * 3), rotate(-(2 + 3) * 10 % 360)")?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        // constant expressions are folded during expansion
+        [
+            Operation::Scale(ast::Expr::Num(s)),
+            Operation::Rotate(ast::Expr::Num(r)),
+        ] => {
+            assert_eq!((*s, *r), (7., 310.));
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn time_varying_expressions() -> Result<()> {
+    let stmts = parse_statements(
+        "let pulse = 1 + 0.5 * sin(t * 3)\nface: scale(pulse), brighten(lerp(0.5, 1.5, t % 2 / 2)), spin(0.5)",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        [Operation::Scale(s), Operation::Brightness(b), spin] => {
+            assert!(s.is_animated() && b.is_animated());
+            assert!(!spin.is_animated());
+
+            assert_eq!(s.eval(0.), 1.);
+            assert!((s.eval(std::f32::consts::PI / 6.) - 1.5).abs() < 1e-6);
+            assert_eq!((b.eval(0.), b.eval(1.), b.eval(2.5)), (0.5, 1., 0.75));
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn runtime_params() -> Result<()> {
+    let stmts = parse_statements(
+        "let big = intensity * 2\nface: scale(big), spin(speed), brighten(1.5)\nparam intensity = 1.5\nparam speed = -0.25",
+    )?;
+    let params = stmts
+        .iter()
+        .filter_map(|s| match s {
+            ast::Statement::Param(p) => Some((p.name.name.as_str(), p.default)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(params, [("intensity", 1.5), ("speed", -0.25)]);
+
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        [Operation::Scale(s), Operation::Spin(spin), brighten] => {
+            // params can change every frame, so are never folded away
+            assert!(s.is_animated() && spin.is_animated());
+            assert!(!brighten.is_animated());
+
+            let mut values = HashMap::from([("intensity".to_string(), 1.5)]);
+            assert_eq!(s.eval_with(0., &values), 3.);
+            values.insert("intensity".to_string(), 0.25);
+            assert_eq!(s.eval_with(0., &values), 0.5);
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+
+    let err = parse_statements("param x = 1\nlet x = 2").unwrap_err();
+    assert_eq!(err.message, "`x` is already defined");
+    let err = parse_statements("param speed = 1\nface: speed").unwrap_err();
+    assert_eq!(err.message, "expected an operation but `speed` is a number");
+    let err = parse_statements("param speed = 1\nface: spin(sped)").unwrap_err();
+    assert_eq!(err.suggestion.as_deref(), Some("speed"));
+    Ok(())
+}
+
+#[test]
+fn trailing_point_numbers() -> Result<()> {
+    let stmts = parse_statements("face: scale(2.), rotate(-45.)\nmouth#1.above(1.): spin")?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    match &t.operations[..] {
+        [Operation::Scale(f), Operation::Rotate(r)] => {
+            assert_eq!((f.value(), r.value()), (2., -45.))
+        }
+        ops => panic!("Unexpected operations {ops:?}"),
+    }
+    // a face index followed by an adjustment still lexes as a number with
+    // a trailing point
+    let ast::Statement::Transform(t) = &stmts[1] else {
+        panic!("Expected transform")
+    };
+    assert_eq!(t.shape.to_string(), "mouth#1.offset(0, -1)");
+
+    assert!(parse_statements("mouth#1.5.above(1): spin").is_err());
+    let err = parse_statements("mouth#1.5above(1): spin").unwrap_err();
+    assert_eq!(err.message, "face index must be a whole number");
+    Ok(())
+}
+
+#[test]
+fn numbers_too_large() {
+    for (src, column) in [
+        ("mouth#99999999999: spin", 7),
+        ("mouth-99999999999: spin", 7),
+        ("mouth+2147483648: spin", 7),
+        ("mouth#99999999999.above(1): spin", 7),
+        ("rect(99999999999, 0, 1, 1): tile", 6),
+        ("rect(0, 0, 1, 99999999999): tile", 15),
+    ] {
+        let err = parse_statements(src).unwrap_err();
+        assert_eq!(err.message, "number is too large", "{src}");
+        assert_eq!((err.line, err.column), (1, column), "{src}");
+
+        let report = lint(src, &mut NoIncludes, &LintOptions::default()).unwrap_err();
+        assert_eq!(report.message, "number is too large", "{src}");
+    }
+    assert!(parse_statements("mouth-2147483647: spin").is_ok());
+}
+
+#[test]
+fn fractional_translation() {
+    let err = parse_statements("face: spin\nmouth: translate(10, 2.5)").unwrap_err();
+    assert_eq!(err.message, "translate only accepts whole pixels");
+    assert_eq!((err.line, err.column), (2, 1));
+
+    // the rule is checked against values, however they're written
+    for src in [
+        "face: translate(5 / 2, 0)",
+        "let half = 0.5\nface: translate(half, 0)",
+        "face: copy_to(mouth { translate(0, 0.5) })",
+    ] {
+        let err = parse_statements(src).unwrap_err();
+        assert_eq!(err.message, "translate only accepts whole pixels", "{src}");
+    }
+
+    // animated offsets are rounded every frame instead
+    assert!(parse_statements("face: translate(t * 10, 0)").is_ok());
+}
+
+#[test]
+fn gammas_compose() {
+    for src in [
+        "face: gamma(2), invert, gamma(2)",
+        "let grade = sepia(1), gamma(1.5)\nface: gamma(2), grade",
+        "face: gamma(2), hue(90), copy_to(mouth { gamma(0.5) })",
+    ] {
+        assert!(parse_statements(src).is_ok(), "{src}");
+    }
+}
+
+#[cfg(test)]
+fn without_spans(statements: Vec<Statement>) -> Vec<Statement> {
+    statements
+        .into_iter()
+        .map(Statement::without_spans)
+        .collect()
+}
+
+#[cfg(test)]
+fn test_files(files: &[(&str, &str)]) -> std::collections::HashMap<String, String> {
+    files
+        .iter()
+        .map(|(name, src)| (name.to_string(), src.to_string()))
+        .collect()
+}
+
+#[test]
+fn include_splices_statements() -> Result<()> {
+    let mut files = test_files(&[
+        (
+            "lib/pop.eymo",
+            "include \"speed.eymo\"\nlet pop = scale(2), spin(speed)",
+        ),
+        ("lib/speed.eymo", "let speed = 0.5\nface: drift(speed)"),
+    ]);
+    let stmts = parse_statements_with(
+        "leye: pop\ninclude \"lib/pop.eymo\"\ninclude \"lib/../lib/speed.eymo\"\nreye: pop",
+        &mut files,
+    )?;
+
+    let ops = stmts
+        .iter()
+        .map(|s| match s {
+            ast::Statement::Transform(t) => t.operations.len(),
+            s => panic!("Unexpected statement {s:?}"),
+        })
+        .collect::<Vec<_>>();
+    // speed.eymo is only included once
+    assert_eq!(ops, [2, 1, 2]);
+    Ok(())
+}
+
+#[test]
+fn lut_paths_follow_includes() -> Result<()> {
+    let mut files = test_files(&[
+        (
+            "grades/main.eymo",
+            "include \"../lib/warm.eymo\"\nface: lut(\"film.cube\")\nmouth: warm",
+        ),
+        (
+            "lib/warm.eymo",
+            "let warm = lut(\"../luts/warm.cube\")\nleye: lut(\"./teal.cube\")",
+        ),
+    ]);
+    let stmts = parse_file("grades/main.eymo", &mut files)?;
+    assert_eq!(
+        lut_paths(&stmts),
+        ["lib/teal.cube", "grades/film.cube", "luts/warm.cube"]
+    );
+    Ok(())
+}
+
+#[test]
+fn include_errors() {
+    let mut files = test_files(&[
+        ("a.eymo", "include \"lib/b.eymo\""),
+        ("lib/b.eymo", "face: spin\ninclude \"../a.eymo\""),
+        ("typo.eymo", "face: spin\nmouth: scael(2)"),
+    ]);
+
+    let err = parse_file("a.eymo", &mut files).unwrap_err();
+    assert_eq!(err.file.as_deref(), Some("lib/b.eymo"));
+    assert_eq!((err.line, err.column), (2, 1));
+    assert!(
+        err.message
+            .contains("`a.eymo` includes itself (a.eymo -> lib/b.eymo -> a.eymo)"),
+        "{}",
+        err.message
+    );
+
+    let err = parse_statements_with("include \"typo.eymo\"", &mut files).unwrap_err();
+    assert_eq!(err.file.as_deref(), Some("typo.eymo"));
+    assert_eq!((err.line, err.column), (2, 8));
+    assert!(err.render().contains("--> typo.eymo:2:8\n"));
+
+    let err =
+        parse_statements_with("face: spin\ninclude \"missing.eymo\"", &mut files).unwrap_err();
+    assert_eq!(err.file, None);
+    assert_eq!(
+        err.message,
+        "failed to include `missing.eymo`: no such file"
+    );
+
+    let err = parse_statements("include \"a.eymo\"").unwrap_err();
+    assert_eq!(err.start, 0);
+}
+
+#[test]
+fn scenes() -> Result<()> {
+    let stmts = parse_statements(
+        "let pop = scale(2), spin\nface: tile\nat 0s..5s { mouth: pop }\nat 5s..10.5s {\n  face: spin\n  leye: pop, tile\n}\nat 500ms.. {}",
+    )?;
+
+    let windows = stmts
+        .iter()
+        .filter_map(|s| match s {
+            ast::Statement::Scene(scene) => Some((scene.window, scene.transforms.len())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        windows,
+        [
+            (
+                ast::Window {
+                    start: 0.,
+                    end: Some(5.)
+                },
+                1
+            ),
+            (
+                ast::Window {
+                    start: 5.,
+                    end: Some(10.5)
+                },
+                2
+            ),
+            (
+                ast::Window {
+                    start: 0.5,
+                    end: None
+                },
+                0
+            ),
+        ]
+    );
+
+    let ast::Statement::Scene(scene) = &stmts[2] else {
+        panic!("Expected scene")
+    };
+    assert_eq!(scene.transforms[1].operations.len(), 3);
+
+    assert!(windows[0].0.contains(0.) && !windows[0].0.contains(5.));
+    assert!(windows[2].0.contains(100.) && !windows[2].0.contains(0.25));
+    Ok(())
+}
+
+#[test]
+fn looping_timeline() {
+    let windows = [
+        ast::Window {
+            start: 0.,
+            end: Some(5.),
+        },
+        ast::Window {
+            start: 2.,
+            end: Some(8.),
+        },
+    ];
+    assert_eq!(loop_period(&windows), Some(8.));
+    assert_eq!(timeline_position(17., loop_period(&windows)), 1.);
+    assert_eq!(timeline_position(17., None), 17.);
+
+    let windows = [
+        windows[0],
+        ast::Window {
+            start: 2.,
+            end: None,
+        },
+    ];
+    assert_eq!(loop_period(&windows), None);
+}
+
+// A scene spanning the whole loop enters again each time it wraps, so its
+// animations start over
+#[test]
+fn full_loop_scene_restarts() {
+    let mut scenes = [Scene {
+        window: Some(ast::Window {
+            start: 0.,
+            end: Some(5.),
+        }),
+        transforms: Vec::new(),
+        active: false,
+    }];
+    let mut last = 0.;
+    let mut entered = Vec::new();
+    for t in [0., 2., 4.9, 5.1, 7., 10.5] {
+        let position = timeline_position(t, Some(5.));
+        leave_scenes(&mut scenes, last, position);
+        entered.push(!scenes[0].active);
+        scenes[0].active = true;
+        last = position;
+    }
+    assert_eq!(entered, [true, false, false, true, false, true]);
+}
+
+// Transforms kept by a reload, by their index in the old config
+#[test]
+fn reload_keeps_unchanged_transforms() -> Result<()> {
+    let transforms = |src: &str| -> Result<Vec<_>> {
+        let mut transforms = Vec::new();
+        for s in parse_statements(src)? {
+            match s {
+                Statement::Transform(t) => transforms.push((None, t)),
+                Statement::Scene(scene) => transforms.extend(
+                    scene
+                        .transforms
+                        .into_iter()
+                        .map(|t| (Some(scene.window), t)),
+                ),
+                _ => {}
+            }
+        }
+        Ok(transforms)
+    };
+    let reload = |before: &str, after: &str| -> Result<Vec<Option<usize>>> {
+        let mut previous = transforms(before)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, (window, t))| (window, (i, t)))
+            .collect();
+        Ok(transforms(after)?
+            .into_iter()
+            .map(|(window, t)| take_unchanged(&mut previous, window, &t).map(|(i, _)| i))
+            .collect())
+    };
+
+    let src = "face: spin\nat 0s..2s { mouth: scale(2) }";
+    assert_eq!(reload(src, src)?, [Some(0), Some(1)]);
+    // edited
+    assert_eq!(
+        reload(src, "face: spin(2)\nat 0s..2s { mouth: scale(2) }")?,
+        [None, Some(1)]
+    );
+    assert_eq!(
+        reload(src, "face: spin\nat 0s..3s { mouth: scale(2) }")?,
+        [Some(0), None]
+    );
+    // moved, to elsewhere in the file but not into another scene
+    assert_eq!(
+        reload(src, "at 0s..2s {\n  mouth: scale(2)\n}\n\nface: spin")?,
+        [Some(1), Some(0)]
+    );
+    assert_eq!(
+        reload(src, "at 0s..2s { face: spin }\nmouth: scale(2)")?,
+        [None, None]
+    );
+    // identical transforms are each kept once
+    assert_eq!(
+        reload(
+            "face: spin\nface: spin",
+            "face: spin\nface: spin\nface: spin"
+        )?,
+        [Some(0), Some(1), None]
+    );
+    Ok(())
+}
+
+#[test]
+fn scene_errors() {
+    let err = parse_statements("at 5s..2s { face: spin }").unwrap_err();
+    assert_eq!(err.message, "scene must end after it starts");
+    assert_eq!((err.start, err.end), (3, 9));
+
+    let err = parse_statements("at 1s.. { face: spin }\nloop").unwrap_err();
+    assert_eq!(err.message, "loop needs every scene to have an end time");
+    assert_eq!((err.line, err.column), (2, 1));
+
+    let err = parse_statements("face: spin\nloop").unwrap_err();
+    assert_eq!(err.message, "loop needs at least one scene to repeat");
+
+    assert!(parse_statements("at 0s..1s { face: spin }\nloop").is_ok());
+}
+
+#[test]
+fn when_blocks() -> Result<()> {
+    let stmts = parse_statements(
+        "when mouth_open(face) > 0.4 {\n  leye: scale(3)\n  mouth: spin\n}\nface: tile\nat 0s..1s {\n  when blink(leye) { face: flip(both) }\n  nose: spin\n}",
+    )?;
+    assert_eq!(stmts.len(), 4);
+
+    let conditions = stmts
+        .iter()
+        .flat_map(|s| match s {
+            ast::Statement::Transform(t) => Vec::from([&t.condition]),
+            ast::Statement::Scene(scene) => scene.transforms.iter().map(|t| &t.condition).collect(),
+            s => panic!("Unexpected statement {s:?}"),
+        })
+        .map(|c| c.as_ref().map(|c| (c.metric.kind, c.cmp, c.threshold)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        conditions,
+        [
+            Some((ast::MetricKind::MouthOpen, ast::Cmp::Gt, 0.4)),
+            Some((ast::MetricKind::MouthOpen, ast::Cmp::Gt, 0.4)),
+            None,
+            Some((
+                ast::MetricKind::EyeOpen,
+                ast::Cmp::Lt,
+                ast::Condition::EYE_OPEN
+            )),
+            None,
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn ordered_blocks() -> Result<()> {
+    let stmts = parse_statements(
+        "ordered {\n  mouth: rotate(45), translate(100, 0)\n  when blink(leye) { nose: spin }\n}\nface: tile\nat 0s..1s { ordered { leye: scale(2), scale(0.5) } }",
+    )?;
+
+    let ordered = stmts
+        .iter()
+        .flat_map(|s| match s {
+            ast::Statement::Transform(t) => Vec::from([t]),
+            ast::Statement::Scene(scene) => scene.transforms.iter().collect(),
+            s => panic!("Unexpected statement {s:?}"),
+        })
+        .map(|t| (t.ordered, t.condition.is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ordered,
+        [(true, false), (true, true), (false, false), (true, false)]
+    );
+    Ok(())
+}
+
+#[test]
+fn when_applies_per_face() -> Result<()> {
+    let mut detection = test_detection(3);
+    detection[1].mouth_open = 0.6;
+    detection[2].l_eye_open = 0.1;
+
+    let ops_on = |src: &str| -> Result<Vec<u32>> {
+        let stmts = parse_statements(src)?;
+        let ast::Statement::Transform(t) = &stmts[0] else {
+            panic!("Expected transform")
+        };
+        Ok(shape_ops("0".to_string(), t, &detection, TEST_FRAME)
+            .iter()
+            .map(|op| op.base.center().x / 200)
+            .collect())
+    };
+
+    assert_eq!(ops_on("when mouth_open(face) { mouth: scale(2) }")?, [1]);
+    assert_eq!(
+        ops_on("when mouth_open(face) < 0.5 { mouth: scale(2) }")?,
+        [0, 2]
+    );
+    // conditions can look at other faces, relative to the transformed one
+    assert_eq!(ops_on("when mouth_open(face+1) { mouth: scale(2) }")?, [0]);
+    assert_eq!(ops_on("when blink(leye) { face: flip(both) }")?, [2]);
+    assert_eq!(ops_on("when blink(reye) { face: flip(both) }")?, [0u32; 0]);
+    assert_eq!(ops_on("when mouth_open(face#1) { face#0: tile }")?, [0]);
+    assert_eq!(
+        ops_on("when mouth_open(face#2) { face#0: tile }")?,
+        [0u32; 0]
+    );
+    // rects apply when any face meets the condition
+    assert_eq!(
+        ops_on("when mouth_open(face) { rect(0 0 10 10): tile }")?.len(),
+        1
+    );
+    Ok(())
+}
+
+#[test]
+fn frame_shapes() -> Result<()> {
+    use crate::shapes::rect::Rect;
+
+    let stmts = parse_statements(
+        "rect(10% 10% 30% 50%): copy_to(rect(0, 0, 12.5%, 25%))\npoly((0, 0), (50%, 100%), (100%, 0)): tile\nrect(10, 20, 30, 40): tile",
+    )?;
+    let ops = |frame: (u32, u32)| {
+        stmts
+            .iter()
+            .map(|s| {
+                let ast::Statement::Transform(t) = s else {
+                    panic!("Expected transform")
+                };
+                shape_ops("0".to_string(), t, &Vec::new(), frame).remove(0)
+            })
+            .collect::<Vec<_>>()
+    };
+    let bounds = |s: &Shape| {
+        let r = Rect::from(s.clone());
+        (r.left(), r.top(), r.right(), r.bottom())
+    };
+    let points = |s: &Shape| s.points().iter().map(|p| (p.x, p.y)).collect::<Vec<_>>();
+
+    let small = ops((640, 480));
+    assert_eq!(bounds(&small[0].base), (64, 48, 64 + 192, 48 + 240));
+    assert_eq!(bounds(small[0].dest.as_ref().unwrap()), (0, 0, 80, 120));
+    assert_eq!(points(&small[1].base), [(0, 0), (320, 480), (640, 0)]);
+    assert_eq!(bounds(&small[2].base), (10, 20, 40, 60));
+
+    // the same config follows the frame size, except for pixel units
+    let large = ops((1920, 1080));
+    assert_eq!(bounds(&large[0].base), (192, 108, 192 + 576, 108 + 540));
+    assert_eq!(points(&large[1].base), [(0, 0), (960, 1080), (1920, 0)]);
+    assert_eq!(bounds(&large[2].base), (10, 20, 40, 60));
+
+    let err = parse_statements("poly((0, 0), (10, 10)): tile").unwrap_err();
+    assert_eq!(err.message, "poly needs at least 3 points");
+    Ok(())
+}
+
+#[test]
+fn anchored_shapes() -> Result<()> {
+    use crate::shapes::rect::Rect;
+
+    let mut detection = test_detection(2);
+    // second head is tilted a quarter turn
+    detection[1].rot = std::f32::consts::FRAC_PI_2;
+
+    let stmts = parse_statements(
+        "mouth: copy_to(forehead.above(0.5), leye.offset(-1.5, 0).grow(2), mouth#1.below(1))",
+    )?;
+    let ast::Statement::Transform(t) = &stmts[0] else {
+        panic!("Expected transform")
+    };
+    let dests = shape_ops("0".to_string(), t, &detection, TEST_FRAME)
+        .into_iter()
+        .map(|op| Rect::from(op.dest.unwrap()))
+        .collect::<Vec<_>>();
+
+    // parts are 20x20, so offsets are in steps of 20px
+    assert_eq!(dests[0], Rect::from_tl(80, 0, 20, 20));
+    assert_eq!(dests[1], Rect::from_tl(0, 50, 40, 40));
+    // offsets follow the tilt of the head the part is on, so "below" on
+    // the second face points along the frame's x axis
+    assert_eq!(dests[2], Rect::from_tl(200 + 80 - 20, 140, 20, 20));
+    assert_eq!(dests[3], Rect::from_tl(200 + 80 + 10, 10, 20, 20));
+    assert_eq!(dests[4], Rect::from_tl(200 + 30, 20, 40, 40));
+    assert_eq!(dests[5], dests[2]);
+    Ok(())
+}
+
+#[test]
+fn json_round_trip() -> Result<()> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let src = std::fs::read_to_string(&path)?;
+        let json = to_json(&parse_ast(&src)?);
+        assert_eq!(
+            without_spans(statements_from_json(&json)?),
+            without_spans(parse_statements(&src)?),
+            "{}",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn json_statements() -> Result<()> {
+    let json = r#"[
+        {"let": {"name": "pop", "value": {"operations": [{"scale": 1.5}]}}},
+        {"transform": {
+            "shape": {"face_ref": {"part": "leye", "face_idx": {"relative": 1}}},
+            "operations": [
+                {"ref": "pop"},
+                {"rotate": {"bin_op": [{"var": "speed"}, "mul", "time"]}},
+                {"copy_to": [{"frame": {"rect": [{"px": 0}, {"px": 0}, {"percent": 10}, {"px": 20}]}}]}
+            ]
+        }},
+        {"let": {"name": "speed", "value": {"number": 2}}},
+        {"scene": {
+            "window": {"start": 0, "end": 2.5},
+            "transforms": [{"shape": {"face_ref": {"part": "mouth"}}, "operations": [{"flip": "both"}]}]
+        }},
+        {"loop": null}
+    ]"#;
+    assert_eq!(
+        without_spans(statements_from_json(json)?),
+        without_spans(parse_statements(
+            "let pop = scale(1.5)
+            leye+1: pop, rotate(speed * t), copy_to(rect(0, 0, 10%, 20))
+            let speed = 2
+            at 0s..2.5s { mouth: flip(both) }
+            loop"
+        )?)
+    );
+    Ok(())
+}
+
+#[test]
+fn json_errors() {
+    let err = statements_from_json(
+        r#"[
+        {"transform": {"shape": {"face_ref": {"part": "eye"}}, "operations": []}}
+    ]"#,
+    )
+    .unwrap_err();
+    assert_eq!(err.line, 2);
+    assert!(
+        err.message.starts_with("unknown variant `eye`"),
+        "{}",
+        err.message
+    );
+
+    let err = statements_from_json(r#"[{"include": {"path": "lib.eymo"}}]"#).unwrap_err();
+    assert_eq!(err.message, "includes are not supported in JSON configs");
+
+    let err = statements_from_json(
+        r#"[
+        {"transform": {"shape": {"face_ref": {"part": "face"}}, "operations": [{"spin": 1}]}},
+        {"transform": {"shape": {"face_ref": {"part": "face"}}, "operations": [{"ref": "pop"}]}}
+    ]"#,
+    )
+    .unwrap_err();
+    assert_eq!(err.message, "unknown name `pop`");
+    // the whole statement the name is in
+    assert_eq!((err.line, err.column), (3, 9));
+    assert!(
+        err.render().contains(r#"{"ref": "pop"}"#),
+        "{}",
+        err.render()
+    );
+}
+
+#[test]
+fn json_checked_like_source() {
+    let face = r#""shape": {"face_ref": {"part": "face"}}"#;
+    for (json, message) in [
+        (
+            r#"{"transform": {"shape": {"frame": {"poly": []}}, "operations": []}}"#.to_string(),
+            "poly needs at least 3 points",
+        ),
+        (
+            format!(
+                r#"{{"transform": {{{face}, "operations": [{{"swap_with": {{"frame": {{"poly": [[{{"px": 0}}, {{"px": 0}}]]}}}}}}]}}}}"#
+            ),
+            "poly needs at least 3 points",
+        ),
+        (
+            r#"{"scene": {"window": {"start": 2, "end": 1}, "transforms": []}}"#.to_string(),
+            "scene must end after it starts",
+        ),
+        (
+            format!(r#"{{"transform": {{{face}, "operations": [{{"translate": [0, 0.5]}}]}}}}"#),
+            "translate only accepts whole pixels",
+        ),
+        (
+            format!(
+                r#"{{"transform": {{{face}, "operations": [{{"cycle": {{"shapes": []}}}}]}}}}"#
+            ),
+            "cycle needs at least one shape",
+        ),
+    ] {
+        let json = format!("[\n  {json}\n]");
+        let err = statements_from_json(&json).unwrap_err();
+        assert_eq!(err.message, message, "{json}");
+        assert_eq!((err.line, err.column), (2, 3), "{json}");
+    }
+}
+
+#[test]
+fn json_call_arity() {
+    let err = statements_from_json(r#"[{"transform": {"shape": {"face_ref": {"part": "face"}}, "operations": [{"spin": {"call": ["sin", []]}}]}}]"#)
+        .unwrap_err();
+    assert_eq!(err.message, "sin takes 1 argument, not 0");
+
+    let err = statements_from_json(
+        r#"[{"let": {"name": "x", "value": {"number": {"call": ["lerp", [1, 2]]}}}}]"#,
+    )
+    .unwrap_err();
+    assert_eq!(err.message, "lerp takes 3 arguments, not 2");
+}
@@ -1,59 +1,527 @@
+use crate::shapes::point::Point;
+use crate::shapes::polygon::Polygon;
 use crate::shapes::rect::Rect;
-pub use crate::transform::FlipVariant;
-use std::fmt;
+use crate::shapes::shape;
+pub use crate::transform::{BlendMode, FlipVariant, PixelateMode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Statement {
     Transform(Transform),
+    Let(Binding),
+    Param(Param),
+    Include(Include),
+    Scene(Scene),
+    // restart the timeline once the last scene ends
+    Loop(Span),
 }
 
-#[derive(Debug)]
+impl Statement {
+    // Point every span in the statement at `span`, for statements with no
+    // source of their own like those from JSON
+    pub(crate) fn locate(&mut self, span: Span) {
+        match self {
+            Statement::Transform(t) => t.locate(span),
+            Statement::Let(b) => {
+                b.name.span = span;
+                match &mut b.value {
+                    BindingValue::Number(e) => e.locate(span),
+                    BindingValue::Operations(ops) => ops.iter_mut().for_each(|o| o.locate(span)),
+                }
+            }
+            Statement::Param(p) => p.name.span = span,
+            Statement::Include(i) => i.span = span,
+            Statement::Scene(scene) => {
+                scene.span = span;
+                scene.transforms.iter_mut().for_each(|t| t.locate(span));
+            }
+            Statement::Loop(s) => *s = span,
+        }
+    }
+
+    // The statement as if it had no source, for comparing trees parsed from
+    // differently laid out configs
+    #[cfg(test)]
+    pub(crate) fn without_spans(mut self) -> Self {
+        self.locate(Span::default());
+        self
+    }
+}
+
+// Byte offsets into config source
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+// Spans are left out of JSON, which has no source to point into
+impl Serialize for Span {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit()
+    }
+}
+
+impl<'de> Deserialize<'de> for Span {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <()>::deserialize(deserializer)?;
+        Ok(Span::default())
+    }
+}
+
+// Written as just the name in JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+impl From<String> for Ident {
+    fn from(name: String) -> Self {
+        Self {
+            name,
+            span: Span::default(),
+        }
+    }
+}
+
+impl From<Ident> for String {
+    fn from(ident: Ident) -> Self {
+        ident.name
+    }
+}
+
+// `let <name> = <value>`, expanded away before interpretation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub name: Ident,
+    pub value: BindingValue,
+}
+
+// `param <name> = <default>`, a number the host can change while the config
+// runs, with `Interpreter::set_param`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: Ident,
+    pub default: f32,
+}
+
+// `include "<path>"`, replaced with the statements of the included file
+// before interpretation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Include {
+    pub path: String,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingValue {
+    Number(Expr),
+    Operations(Vec<Operation>),
+}
+
+// Numeric operation argument. Anything depending on `t` (seconds since
+// the interpreter started) or a `param` is re-evaluated every frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expr {
+    Var(Ident),
+    // names of `param`s are replaced with these during expansion
+    Param(Ident),
+    Time,
+    Neg(Box<Expr>),
+    BinOp(Box<Expr>, BinOp, Box<Expr>),
+    Call(Func, Vec<Expr>),
+    // plain numbers in JSON
+    #[serde(untagged)]
+    Num(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Func {
+    Sin,
+    Cos,
+    Lerp,
+}
+
+impl Func {
+    // Name of the function as written in config source
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Func::Sin => "sin",
+            Func::Cos => "cos",
+            Func::Lerp => "lerp",
+        }
+    }
+
+    // Number of arguments the function takes, checked before anything is
+    // evaluated
+    pub fn arity(&self) -> usize {
+        match self {
+            Func::Sin | Func::Cos => 1,
+            Func::Lerp => 3,
+        }
+    }
+}
+
+impl Expr {
+    // Value of an expression without params at `t` seconds. Variables are
+    // replaced with their bound values during expansion, so expressions
+    // can only be evaluated once expanded.
+    pub fn eval(&self, t: f32) -> f32 {
+        self.eval_with(t, &HashMap::new())
+    }
+
+    // Value of an expression at `t` seconds, with the current value of
+    // each param
+    pub fn eval_with(&self, t: f32, params: &HashMap<String, f32>) -> f32 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Var(id) => unreachable!("`{}` should have been substituted", id.name),
+            Expr::Param(id) => params.get(&id.name).copied().unwrap_or(0.),
+            Expr::Time => t,
+            Expr::Neg(e) => -e.eval_with(t, params),
+            Expr::BinOp(l, op, r) => {
+                let (l, r) = (l.eval_with(t, params), r.eval_with(t, params));
+                match op {
+                    BinOp::Add => l + r,
+                    BinOp::Sub => l - r,
+                    BinOp::Mul => l * r,
+                    BinOp::Div => l / r,
+                    BinOp::Rem => l.rem_euclid(r),
+                }
+            }
+            Expr::Call(f, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.eval_with(t, params))
+                    .collect::<Vec<_>>();
+                match (f, &args[..]) {
+                    (Func::Sin, [x]) => x.sin(),
+                    (Func::Cos, [x]) => x.cos(),
+                    (Func::Lerp, [a, b, x]) => a + (b - a) * x,
+                    (f, args) => {
+                        unreachable!("{} called with {} arguments", f.keyword(), args.len())
+                    }
+                }
+            }
+        }
+    }
+
+    // Value of an expression that doesn't depend on time
+    pub fn value(&self) -> f32 {
+        self.eval(0.)
+    }
+
+    fn locate(&mut self, span: Span) {
+        match self {
+            Expr::Var(id) | Expr::Param(id) => id.span = span,
+            Expr::Neg(e) => e.locate(span),
+            Expr::BinOp(l, _, r) => {
+                l.locate(span);
+                r.locate(span);
+            }
+            Expr::Call(_, args) => args.iter_mut().for_each(|a| a.locate(span)),
+            Expr::Num(_) | Expr::Time => {}
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        match self {
+            Expr::Num(_) | Expr::Var(_) => false,
+            Expr::Time | Expr::Param(_) => true,
+            Expr::Neg(e) => e.is_animated(),
+            Expr::BinOp(l, _, r) => l.is_animated() || r.is_animated(),
+            Expr::Call(_, args) => args.iter().any(|a| a.is_animated()),
+        }
+    }
+}
+
+// `at <start>..<end> { <transforms> }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub window: Window,
+    pub transforms: Vec<Transform>,
+    // location of the window, for errors about it
+    #[serde(skip)]
+    pub span: Span,
+}
+
+// Stretch of the timeline a scene is active for, in seconds. Scenes without
+// an end stay active once they start.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    pub start: f32,
+    pub end: Option<f32>,
+}
+
+impl Window {
+    pub fn contains(&self, t: f32) -> bool {
+        t >= self.start && self.end.is_none_or(|end| t < end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transform {
     pub shape: Shape,
     pub operations: Vec<Operation>,
+    // set for transforms inside a `when` block, which only apply to the
+    // faces meeting the condition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    // set for transforms inside an `ordered` block, whose geometric
+    // operations compose in the order they're written
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ordered: bool,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl Transform {
+    // Whether the transforms do the same thing, wherever they're written
+    pub fn same_ignoring_span(&self, other: &Transform) -> bool {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        a.locate(Span::default());
+        b.locate(Span::default());
+        a == b
+    }
+
+    fn locate(&mut self, span: Span) {
+        self.span = span;
+        self.operations.iter_mut().for_each(|o| o.locate(span));
+    }
+}
+
+// `<metric> > <threshold>` or `<metric> < <threshold>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    pub metric: Metric,
+    pub cmp: Cmp,
+    pub threshold: f32,
+}
+
+impl Condition {
+    // Thresholds for conditions written as just the metric, eg.
+    // `when mouth_open(face)`, or `when blink(leye)`
+    pub const MOUTH_OPEN: f32 = 0.35;
+    pub const EYE_OPEN: f32 = 0.2;
+
+    pub fn is_open(metric: Metric) -> Self {
+        let threshold = match metric.kind {
+            MetricKind::MouthOpen => Self::MOUTH_OPEN,
+            MetricKind::EyeOpen => Self::EYE_OPEN,
+        };
+
+        Self {
+            metric,
+            cmp: Cmp::Gt,
+            threshold,
+        }
+    }
+
+    pub fn blink(face: FaceRef) -> Self {
+        Self {
+            metric: Metric {
+                kind: MetricKind::EyeOpen,
+                face,
+            },
+            cmp: Cmp::Lt,
+            threshold: Self::EYE_OPEN,
+        }
+    }
+
+    pub fn holds(&self, value: f32) -> bool {
+        match self.cmp {
+            Cmp::Gt => value > self.threshold,
+            Cmp::Lt => value < self.threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metric {
+    pub kind: MetricKind,
+    // face to measure, relative to the face being transformed
+    pub face: FaceRef,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    MouthOpen,
+    // eye parts measure that eye, other parts average both eyes
+    EyeOpen,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cmp {
+    Gt,
+    Lt,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Shape {
     FaceRef(FaceRef),
-    Rect(Rect),
+    Frame(FrameShape),
+    // `leye|reye`, every shape in the list
+    Union(Vec<Shape>),
+}
+
+impl Shape {
+    // Shapes making up this one, which is just itself unless it's a union
+    pub fn members(&self) -> Vec<&Shape> {
+        match self {
+            Shape::Union(shapes) => shapes.iter().flat_map(|s| s.members()).collect(),
+            s => Vec::from([s]),
+        }
+    }
+}
+
+// Shape positioned on the frame rather than on a detected face
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameShape {
+    // `rect(x, y, w, h)`
+    Rect(Length, Length, Length, Length),
+    // `poly((x, y), ...)`
+    Poly(Vec<(Length, Length)>),
+}
+
+impl FrameShape {
+    // Pixel shape on a frame of the given size, so configs using
+    // percentages work at any camera resolution
+    pub fn resolve(&self, (width, height): (u32, u32)) -> shape::Shape {
+        match self {
+            FrameShape::Rect(x, y, w, h) => Rect::from_tl(
+                x.resolve(width),
+                y.resolve(height),
+                w.resolve(width),
+                h.resolve(height),
+            )
+            .into(),
+            FrameShape::Poly(points) => Polygon::new(
+                points
+                    .iter()
+                    .map(|(x, y)| Point {
+                        x: x.resolve(width),
+                        y: y.resolve(height),
+                    })
+                    .collect(),
+            )
+            .into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Length {
+    Px(u32),
+    // percentage of the frame width for x coordinates, height for y
+    Percent(f32),
+}
+
+impl Length {
+    pub fn resolve(self, frame_len: u32) -> u32 {
+        match self {
+            Length::Px(px) => px,
+            Length::Percent(pct) => (frame_len as f32 * pct / 100.).round() as u32,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FaceRef {
     pub part: FacePart,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub face_idx: Option<FaceIdx>,
+    // applied in order to the part's shape, eg. `face.above(0.5).grow(2)`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjustments: Vec<Adjustment>,
 }
 
-impl fmt::Display for FaceRef {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.face_idx {
-            Some(fi) => write!(f, "{}{}", self.part, fi),
-            None => write!(f, "{}", self.part),
+// Shape anchored to a face part, in units of the part's own size so it
+// scales with the face
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Adjustment {
+    // `offset(x, y)`, `above(y)` or `below(y)`, following the head's tilt
+    Offset(f32, f32),
+    // `grow(mag)`, scaling the part around its center
+    Grow(f32),
+}
+
+impl Adjustment {
+    // `rot` is the head tilt of the face the part belongs to, in radians
+    pub fn apply(&self, part: &mut Polygon, rot: f32) {
+        match *self {
+            Adjustment::Offset(x, y) => {
+                let (w, h) = part.oriented_size(rot);
+                let (x, y) = (x * w, y * h);
+                let (sin, cos) = rot.sin_cos();
+                part.shift(x * cos - y * sin, x * sin + y * cos);
+            }
+            Adjustment::Grow(mag) => {
+                part.grow(mag);
+            }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FaceIdx {
     Absolute(u32),
     Relative(i32),
+    // `@largest` etc., picking a face by where it is rather than its index
+    Select(Selector),
 }
 
-impl fmt::Display for FaceIdx {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::Absolute(i) => write!(f, "abs{i}"),
-            Self::Relative(i) => write!(f, "rel{i}"),
-        }
-    }
+// Picks a single face out of those detected. Ties go to the face detected
+// first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selector {
+    Largest,
+    Smallest,
+    Leftmost,
+    Rightmost,
+    // closest to a point given as fractions of the frame width and height
+    Nearest(f32, f32),
 }
 
-#[derive(Debug)]
+impl Selector {
+    pub const KEYWORDS: [&str; 5] = ["largest", "smallest", "leftmost", "rightmost", "nearest"];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FacePart {
+    #[serde(rename = "leye")]
     LEye,
+    #[serde(rename = "reye")]
     REye,
+    #[serde(rename = "leye_region")]
     LEyeRegion,
+    #[serde(rename = "reye_region")]
     REyeRegion,
     Face,
     Mouth,
@@ -61,25 +529,302 @@ pub enum FacePart {
     Forehead,
 }
 
-impl fmt::Display for FacePart {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+impl FacePart {
+    pub const ALL: [FacePart; 8] = [
+        FacePart::LEye,
+        FacePart::REye,
+        FacePart::LEyeRegion,
+        FacePart::REyeRegion,
+        FacePart::Face,
+        FacePart::Mouth,
+        FacePart::Nose,
+        FacePart::Forehead,
+    ];
+
+    // Name of the part as written in config source
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            FacePart::LEye => "leye",
+            FacePart::REye => "reye",
+            FacePart::LEyeRegion => "leye_region",
+            FacePart::REyeRegion => "reye_region",
+            FacePart::Face => "face",
+            FacePart::Mouth => "mouth",
+            FacePart::Nose => "nose",
+            FacePart::Forehead => "forehead",
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Operation {
     Tile,
-    Scale(f32),
-    Rotate(f32),
-    CopyTo(Vec<Shape>),
+    Scale(Expr),
+    Rotate(Expr),
+    CopyTo(Vec<CopyTarget>),
     SwapWith(Shape),
-    Translate(i32, i32),
+    // Moves the transform's shape into the first of `shapes`, each of
+    // those into the next, and the last back into the transform's shape.
+    // With a period in seconds it moves one more step every period.
+    Cycle {
+        shapes: Vec<Shape>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        period: Option<f32>,
+    },
+    Translate(Expr, Expr),
     Flip(FlipVariant),
-    Drift(f32, f32),
-    Spin(f32),
-    Brightness(f32),
-    Saturation(f32),
-    Chans(f32, f32, f32),
-    Reshape(f32, f32, f32, f32),
+    Drift(Expr, Expr),
+    Spin(Expr),
+    #[serde(rename = "brighten")]
+    Brightness(Expr),
+    #[serde(rename = "saturate")]
+    Saturation(Expr),
+    #[serde(rename = "channels")]
+    Chans(Expr, Expr, Expr),
+    Reshape(Expr, Expr, Expr, Expr),
+    // Gaussian blur of the pixels inside the shape, radius in pixels
+    Blur(Expr),
+    // Mosaic of the shape's contents, block size in source pixels
+    Pixelate(Expr, PixelateMode),
+    // Colour operations, composed in the order they're written
+    Hue(Expr),
+    Contrast(Expr),
+    Gamma(Expr),
+    Invert,
+    Grayscale,
+    Sepia(Expr),
+    // Colour grading with a `.cube` lookup table, mixed in by `strength`
+    Lut {
+        path: String,
+        strength: Expr,
+    },
+    // How the drawn shape combines with what's beneath it
+    Opacity(Expr),
+    Blend(BlendMode),
+    // Reference to a `let` bound group of operations
+    Ref(Ident),
+}
+
+// Destination of a `copy_to`, with operations applied to that copy alone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "CopyTargetJson", into = "CopyTargetJson")]
+pub struct CopyTarget {
+    pub shape: Shape,
+    pub operations: Vec<Operation>,
+}
+
+// Written as just the shape in JSON when it has no operations
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CopyTargetJson {
+    Shape(Shape),
+    Target {
+        shape: Shape,
+        operations: Vec<Operation>,
+    },
+}
+
+impl From<CopyTargetJson> for CopyTarget {
+    fn from(json: CopyTargetJson) -> Self {
+        match json {
+            CopyTargetJson::Shape(shape) => Self {
+                shape,
+                operations: Vec::new(),
+            },
+            CopyTargetJson::Target { shape, operations } => Self { shape, operations },
+        }
+    }
+}
+
+impl From<CopyTarget> for CopyTargetJson {
+    fn from(target: CopyTarget) -> Self {
+        match target.operations.is_empty() {
+            true => Self::Shape(target.shape),
+            false => Self::Target {
+                shape: target.shape,
+                operations: target.operations,
+            },
+        }
+    }
+}
+
+// Argument to `cycle` as parsed, before the period is separated out
+pub(crate) enum CycleArg {
+    Shape(Shape),
+    Period(f32),
+}
+
+impl Operation {
+    pub fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Operation::Scale(e)
+            | Operation::Rotate(e)
+            | Operation::Spin(e)
+            | Operation::Brightness(e)
+            | Operation::Saturation(e)
+            | Operation::Blur(e)
+            | Operation::Pixelate(e, _)
+            | Operation::Hue(e)
+            | Operation::Contrast(e)
+            | Operation::Gamma(e)
+            | Operation::Sepia(e)
+            | Operation::Lut { strength: e, .. }
+            | Operation::Opacity(e) => Vec::from([e]),
+            Operation::Translate(a, b) | Operation::Drift(a, b) => Vec::from([a, b]),
+            Operation::Chans(r, g, b) => Vec::from([r, g, b]),
+            Operation::Reshape(a, b, c, d) => Vec::from([a, b, c, d]),
+            Operation::Tile
+            | Operation::CopyTo(_)
+            | Operation::SwapWith(_)
+            | Operation::Cycle { .. }
+            | Operation::Flip(_)
+            | Operation::Invert
+            | Operation::Grayscale
+            | Operation::Blend(_)
+            | Operation::Ref(_) => Vec::new(),
+        }
+    }
+
+    fn locate(&mut self, span: Span) {
+        for e in self.exprs_mut() {
+            e.locate(span);
+        }
+        match self {
+            Operation::Ref(id) => id.span = span,
+            Operation::CopyTo(targets) => {
+                for target in targets {
+                    target.operations.iter_mut().for_each(|o| o.locate(span));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Operation::Scale(e)
+            | Operation::Rotate(e)
+            | Operation::Spin(e)
+            | Operation::Brightness(e)
+            | Operation::Saturation(e)
+            | Operation::Blur(e)
+            | Operation::Pixelate(e, _)
+            | Operation::Hue(e)
+            | Operation::Contrast(e)
+            | Operation::Gamma(e)
+            | Operation::Sepia(e)
+            | Operation::Lut { strength: e, .. }
+            | Operation::Opacity(e) => Vec::from([e]),
+            Operation::Translate(a, b) | Operation::Drift(a, b) => Vec::from([a, b]),
+            Operation::Chans(r, g, b) => Vec::from([r, g, b]),
+            Operation::Reshape(a, b, c, d) => Vec::from([a, b, c, d]),
+            Operation::Tile
+            | Operation::CopyTo(_)
+            | Operation::SwapWith(_)
+            | Operation::Cycle { .. }
+            | Operation::Flip(_)
+            | Operation::Invert
+            | Operation::Grayscale
+            | Operation::Blend(_)
+            | Operation::Ref(_) => Vec::new(),
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        let targets_animated = match self {
+            Operation::CopyTo(targets) => targets
+                .iter()
+                .any(|t| t.operations.iter().any(|o| o.is_animated())),
+            _ => false,
+        };
+        targets_animated || self.exprs().iter().any(|e| e.is_animated())
+    }
+
+    // Whether the operation is composed into the colour stage in order
+    pub fn is_colour(&self) -> bool {
+        matches!(
+            self,
+            Operation::Hue(_)
+                | Operation::Contrast(_)
+                | Operation::Gamma(_)
+                | Operation::Invert
+                | Operation::Grayscale
+                | Operation::Sepia(_)
+        )
+    }
+
+    // Whether the operation can be given to a single `copy_to` destination
+    pub fn per_destination(&self) -> bool {
+        !matches!(
+            self,
+            Operation::Tile
+                | Operation::CopyTo(_)
+                | Operation::SwapWith(_)
+                | Operation::Cycle { .. }
+                | Operation::Lut { .. }
+        )
+    }
+
+    // Name of the operation as written in config source. References are
+    // written as the name they refer to, so have no keyword.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Operation::Tile => "tile",
+            Operation::Scale(_) => "scale",
+            Operation::Rotate(_) => "rotate",
+            Operation::CopyTo(_) => "copy_to",
+            Operation::SwapWith(_) => "swap_with",
+            Operation::Cycle { .. } => "cycle",
+            Operation::Translate(..) => "translate",
+            Operation::Flip(_) => "flip",
+            Operation::Drift(..) => "drift",
+            Operation::Spin(_) => "spin",
+            Operation::Brightness(_) => "brighten",
+            Operation::Saturation(_) => "saturate",
+            Operation::Chans(..) => "channels",
+            Operation::Reshape(..) => "reshape",
+            Operation::Blur(_) => "blur",
+            Operation::Pixelate(..) => "pixelate",
+            Operation::Hue(_) => "hue",
+            Operation::Contrast(_) => "contrast",
+            Operation::Gamma(_) => "gamma",
+            Operation::Invert => "invert",
+            Operation::Grayscale => "grayscale",
+            Operation::Sepia(_) => "sepia",
+            Operation::Lut { .. } => "lut",
+            Operation::Opacity(_) => "opacity",
+            Operation::Blend(_) => "blend",
+            Operation::Ref(_) => "",
+        }
+    }
+
+    // Names of operations as written in config source
+    pub const KEYWORDS: [&str; 25] = [
+        "tile",
+        "scale",
+        "rotate",
+        "copy_to",
+        "swap_with",
+        "cycle",
+        "translate",
+        "flip",
+        "drift",
+        "spin",
+        "brighten",
+        "saturate",
+        "channels",
+        "reshape",
+        "blur",
+        "pixelate",
+        "hue",
+        "contrast",
+        "gamma",
+        "invert",
+        "grayscale",
+        "sepia",
+        "lut",
+        "opacity",
+        "blend",
+    ];
 }
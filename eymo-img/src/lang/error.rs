@@ -0,0 +1,383 @@
+use super::ast::{FacePart, Operation};
+use lalrpop_util::lexer::Token;
+use std::fmt;
+
+type LalrpopError<'a> = lalrpop_util::ParseError<usize, Token<'a>, GrammarError>;
+
+// Error raised by a grammar action for input that parses but isn't valid
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrammarError {
+    pub start: usize,
+    pub end: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    // 1-based line and column (in characters) of the error
+    pub line: u32,
+    pub column: u32,
+    // byte offsets of the offending region within the source
+    pub start: usize,
+    pub end: usize,
+    pub token: Option<String>,
+    pub expected: Vec<String>,
+    pub suggestion: Option<String>,
+    // name of the included file the error is in, None for the root config
+    pub file: Option<String>,
+    // source line the error starts on, for rendering
+    src_line: String,
+    // characters to underline when rendering
+    underline_len: usize,
+}
+
+impl ParseError {
+    pub fn new(src: &str, start: usize, end: usize, message: impl Into<String>) -> Self {
+        let start = char_boundary(src, start);
+        let end = char_boundary(src, end.max(start));
+        let (line, column) = line_col(src, start);
+        let underline_len = src[start..end]
+            .lines()
+            .next()
+            .map(|l| l.chars().count())
+            .unwrap_or(0)
+            .max(1);
+
+        Self {
+            message: message.into(),
+            line,
+            column,
+            start,
+            end,
+            token: None,
+            expected: Vec::new(),
+            suggestion: None,
+            file: None,
+            src_line: src.lines().nth(line as usize - 1).unwrap_or("").to_string(),
+            underline_len,
+        }
+    }
+
+    pub(crate) fn in_file(mut self, file: Option<&str>) -> Self {
+        self.file = file.map(|f| f.to_string());
+        self
+    }
+
+    pub(crate) fn from_lalrpop(src: &str, e: LalrpopError) -> Self {
+        match e {
+            lalrpop_util::ParseError::InvalidToken { location } => {
+                Self::invalid_token(src, location)
+            }
+            lalrpop_util::ParseError::UnrecognizedEof { location, expected } => {
+                Self::new(src, location, location, "unexpected end of input")
+                    .with_expected(expected)
+            }
+            lalrpop_util::ParseError::UnrecognizedToken {
+                token: (start, tok, end),
+                expected,
+            } => Self::unknown_call(src, start).unwrap_or_else(|| {
+                Self::new(
+                    src,
+                    start,
+                    end,
+                    format!("unexpected token `{}`", tok.1.escape_debug()),
+                )
+                .with_token(tok.1)
+                .with_expected(expected)
+            }),
+            lalrpop_util::ParseError::ExtraToken {
+                token: (start, tok, end),
+            } => Self::new(
+                src,
+                start,
+                end,
+                format!("extra token `{}`", tok.1.escape_debug()),
+            )
+            .with_token(tok.1),
+            lalrpop_util::ParseError::User { error } => {
+                Self::new(src, error.start, error.end, error.message)
+            }
+        }
+    }
+
+    // Calls are lexed along with their paren, so a paren right after a name
+    // means the name isn't a known operation
+    fn unknown_call(src: &str, location: usize) -> Option<Self> {
+        if !src[location..].starts_with('(') {
+            return None;
+        }
+
+        let name = word_before(src, location);
+        if name.is_empty() {
+            return None;
+        }
+
+        let start = location - name.len();
+        Some(
+            Self::new(src, start, location, format!("unknown operation `{name}`")).with_token(name),
+        )
+    }
+
+    fn invalid_token(src: &str, location: usize) -> Self {
+        if let Some(e) = Self::unknown_call(src, location) {
+            return e;
+        }
+
+        // outside a word, the token is the (possibly multibyte) character
+        // the lexer stopped at
+        let word = match word_at(src, location) {
+            "" => char_at(src, location),
+            w => w,
+        };
+        let message = match word {
+            "" => "invalid token".to_string(),
+            w => format!("invalid token `{w}`"),
+        };
+        Self::new(src, location, location + word.len(), message).with_token(word)
+    }
+
+    fn with_token(mut self, token: &str) -> Self {
+        if !token.is_empty() {
+            self.token = Some(token.to_string());
+        }
+        self.suggestion = self.suggest();
+        self
+    }
+
+    fn with_expected(mut self, expected: Vec<String>) -> Self {
+        self.expected = expected.iter().map(|e| describe_terminal(e)).collect();
+        self.expected.dedup();
+        self.suggestion = self.suggest();
+        self
+    }
+
+    fn suggest(&self) -> Option<String> {
+        let word = self
+            .token
+            .as_deref()?
+            .trim_end_matches('(')
+            .trim_matches(|c: char| !(c.is_ascii_alphanumeric() || c == '_'));
+        if word.is_empty() {
+            return None;
+        }
+
+        // prefer whatever the parser was expecting, falling back on all
+        // keywords when it couldn't tell us (eg. for unlexable input)
+        let expected = self
+            .expected
+            .iter()
+            .map(|e| e.trim_end_matches('('))
+            .filter(|e| e.chars().all(|c| c.is_ascii_alphabetic() || c == '_'))
+            .collect::<Vec<_>>();
+        let candidates = if expected.is_empty() {
+            keywords()
+        } else {
+            expected
+        };
+
+        closest(word, candidates)
+    }
+
+    // Render error with the offending source line and a caret underline:
+    //
+    // error: invalid token `scael`
+    //  --> 1:8
+    //   |
+    // 1 | mouth: scael(2)
+    //   |        ^^^^^
+    //   = help: did you mean `scale`?
+    pub fn render(&self) -> String {
+        self.render_as("error")
+    }
+
+    // Render with a different label, eg. for lint warnings
+    pub(crate) fn render_as(&self, label: &str) -> String {
+        let gutter = self.line.to_string().len();
+        let pad = " ".repeat(gutter);
+
+        let mut out = format!(
+            "{label}: {}\n{pad}--> {}\n{pad} |\n{} | {}\n{pad} | {}{}\n",
+            self.message,
+            self.location(),
+            self.line,
+            self.src_line,
+            " ".repeat(self.column as usize - 1),
+            "^".repeat(self.underline_len),
+        );
+
+        if !self.expected.is_empty() {
+            out += &format!("{pad} = expected one of: {}\n", self.expected.join(", "));
+        }
+
+        if let Some(suggestion) = &self.suggestion {
+            out += &format!("{pad} = help: did you mean `{suggestion}`?\n");
+        }
+
+        out
+    }
+
+    // `file:line:column`, or just `line:column` in the root config
+    fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{file}:{}:{}", self.line, self.column),
+            None => format!("{}:{}", self.line, self.column),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.location())?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Closest candidate to a (probably misspelled) word, if any are close enough
+pub(crate) fn closest<'a>(
+    word: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    let max_dist = (word.len() / 2).clamp(1, 3);
+    candidates
+        .into_iter()
+        .filter(|c| *c != word)
+        .map(|c| (levenshtein(word, c), c))
+        .filter(|(dist, _)| *dist <= max_dist)
+        .min_by_key(|(dist, _)| *dist)
+        .map(|(_, c)| c.to_string())
+}
+
+fn keywords() -> Vec<&'static str> {
+    Operation::KEYWORDS
+        .iter()
+        .copied()
+        .chain(FacePart::ALL.iter().map(|p| p.keyword()))
+        .collect()
+}
+
+fn line_col(src: &str, offset: usize) -> (u32, u32) {
+    let before = &src[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (line as u32, before[line_start..].chars().count() as u32 + 1)
+}
+
+// First char boundary at or after `offset`, so slicing never splits a
+// character
+fn char_boundary(src: &str, offset: usize) -> usize {
+    let mut offset = offset.min(src.len());
+    while !src.is_char_boundary(offset) {
+        offset += 1;
+    }
+    offset
+}
+
+fn char_at(src: &str, offset: usize) -> &str {
+    let rest = &src[char_boundary(src, offset)..];
+    let len = rest.chars().next().map_or(0, char::len_utf8);
+    &rest[..len]
+}
+
+fn word_at(src: &str, offset: usize) -> &str {
+    let rest = &src[offset.min(src.len())..];
+    let len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    &rest[..len]
+}
+
+fn word_before(src: &str, offset: usize) -> &str {
+    let before = &src[..offset];
+    let start = before
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    &before[start..]
+}
+
+// lalrpop describes terminals as they appear in the grammar, eg. `"scale("`,
+// strip that down to what a user would actually type
+fn describe_terminal(t: &str) -> String {
+    match t.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(lit) => lit.to_string(),
+        None => match t {
+            "UINT" => "integer".to_string(),
+            "F32" => "decimal".to_string(),
+            "IDENT" => "identifier".to_string(),
+            "STRING" => "string".to_string(),
+            "DURATION" => "duration".to_string(),
+            t => t.to_string(),
+        },
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = Vec::with_capacity(b.len() + 1);
+        cur.push(i + 1);
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("scael", "scale"), 2);
+        assert_eq!(levenshtein("mouht", "mouth"), 2);
+        assert_eq!(levenshtein("nose", "nose"), 0);
+        assert_eq!(levenshtein("", "leye"), 4);
+    }
+
+    #[test]
+    fn test_line_col() {
+        let src = "leye: scale(2)\nmouth: spin\n";
+        assert_eq!(line_col(src, 0), (1, 1));
+        assert_eq!(line_col(src, 6), (1, 7));
+        assert_eq!(line_col(src, 15), (2, 1));
+        assert_eq!(line_col(src, 22), (2, 8));
+    }
+
+    #[test]
+    fn test_non_ascii_token() {
+        let e = crate::lang::parse_ast("mouth: é").unwrap_err();
+        assert_eq!(e.message, "invalid token `é`");
+        assert_eq!((e.line, e.column), (1, 8));
+        assert_eq!((e.start, e.end), (7, 9));
+        assert!(e.render().contains("       ^\n"));
+
+        // error regions are snapped to character boundaries
+        let e = ParseError::new("é: scale(2)", 1, 1, "oops");
+        assert_eq!((e.start, e.end), (2, 2));
+    }
+
+    #[test]
+    fn test_describe_terminal() {
+        assert_eq!(describe_terminal("\"scale(\""), "scale(");
+        assert_eq!(describe_terminal("IDENT"), "identifier");
+        assert_eq!(describe_terminal("UINT"), "integer");
+    }
+}
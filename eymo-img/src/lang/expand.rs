@@ -0,0 +1,307 @@
+use super::ast::{Binding, BindingValue, Expr, Ident, Operation, Statement};
+use super::error::{ParseError, closest};
+use super::include::SourceFile;
+use std::collections::HashMap;
+
+// Resolved value of a `let` binding
+#[derive(Debug, Clone)]
+enum Value {
+    Number(Expr),
+    Operations(Vec<Operation>),
+}
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Operations(_) => "a group of operations",
+        }
+    }
+}
+
+// Replace all references to `let` bindings with their values, removing
+// the bindings themselves from the returned statements.
+// Bindings are shared between a config and everything it includes, so
+// statements are tagged with the index of the file they're from, and
+// stay tagged on the way out.
+pub(crate) fn expand(
+    files: &[SourceFile],
+    statements: Vec<(usize, Statement)>,
+) -> Result<Vec<(usize, Statement)>, Box<ParseError>> {
+    let mut bindings: HashMap<String, (usize, Binding)> = HashMap::new();
+    // params are kept for the interpreter, and resolve to themselves
+    let mut params = HashMap::new();
+    let mut rest = Vec::new();
+    for (file, s) in statements {
+        let name = match &s {
+            Statement::Let(b) => &b.name,
+            Statement::Param(p) => &p.name,
+            _ => {
+                rest.push((file, s));
+                continue;
+            }
+        }
+        .clone();
+        if bindings.contains_key(&name.name) || params.contains_key(&name.name) {
+            return Err(error(
+                &files[file],
+                &name,
+                format!("`{}` is already defined", name.name),
+            ));
+        }
+
+        match s {
+            Statement::Let(b) => {
+                bindings.insert(b.name.name.clone(), (file, b));
+            }
+            s => {
+                params.insert(name.name.clone(), Value::Number(Expr::Param(name)));
+                rest.push((file, s));
+            }
+        }
+    }
+
+    check_loops(files, &rest)?;
+
+    let mut expander = Expander {
+        files,
+        file: 0,
+        bindings,
+        resolved: params,
+        stack: Vec::new(),
+    };
+
+    // resolve unused bindings too, so mistakes in them are still reported
+    let mut names = expander
+        .bindings
+        .values()
+        .map(|(file, b)| (*file, b.name.clone()))
+        .collect::<Vec<_>>();
+    names.sort_by_key(|(file, n)| (*file, n.span.start));
+    for (file, name) in &names {
+        expander.file = *file;
+        expander.resolve(name)?;
+    }
+
+    rest.into_iter()
+        .map(|(file, s)| match s {
+            Statement::Transform(mut t) => {
+                expander.file = file;
+                t.operations = expander.operations(t.operations)?;
+                Ok((file, Statement::Transform(t)))
+            }
+            Statement::Scene(mut scene) => {
+                expander.file = file;
+                for t in scene.transforms.iter_mut() {
+                    t.operations = expander.operations(std::mem::take(&mut t.operations))?;
+                }
+                Ok((file, Statement::Scene(scene)))
+            }
+            s => Ok((file, s)),
+        })
+        .collect()
+}
+
+// The timeline can only loop if it has a last scene to loop after
+fn check_loops(
+    files: &[SourceFile],
+    statements: &[(usize, Statement)],
+) -> Result<(), Box<ParseError>> {
+    let scenes = statements
+        .iter()
+        .filter_map(|(_, s)| match s {
+            Statement::Scene(scene) => Some(scene),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for (file, s) in statements {
+        let Statement::Loop(span) = s else {
+            continue;
+        };
+
+        let message = if scenes.is_empty() {
+            "loop needs at least one scene to repeat"
+        } else if scenes.iter().any(|s| s.window.end.is_none()) {
+            "loop needs every scene to have an end time"
+        } else {
+            continue;
+        };
+
+        return Err(Box::new(
+            ParseError::new(&files[*file].src, span.start, span.end, message)
+                .in_file(files[*file].name.as_deref()),
+        ));
+    }
+
+    Ok(())
+}
+
+struct Expander<'a> {
+    files: &'a [SourceFile],
+    // index of the file names are currently being resolved in
+    file: usize,
+    bindings: HashMap<String, (usize, Binding)>,
+    resolved: HashMap<String, Value>,
+    // bindings currently being resolved, for cycle detection
+    stack: Vec<String>,
+}
+
+impl Expander<'_> {
+    fn src(&self) -> &SourceFile {
+        &self.files[self.file]
+    }
+
+    fn resolve(&mut self, id: &Ident) -> Result<Value, Box<ParseError>> {
+        if let Some(v) = self.resolved.get(&id.name) {
+            return Ok(v.clone());
+        }
+
+        let (file, binding) = match self.bindings.get(&id.name) {
+            Some((file, b)) => (*file, b.value.clone()),
+            None => {
+                let mut e = error(self.src(), id, format!("unknown name `{}`", id.name));
+                let names = self.bindings.keys().chain(self.resolved.keys());
+                e.suggestion = closest(&id.name, names.map(|k| k.as_str()));
+                return Err(e);
+            }
+        };
+
+        if let Some(pos) = self.stack.iter().position(|n| *n == id.name) {
+            let mut cycle = self.stack[pos..].to_vec();
+            cycle.push(id.name.clone());
+            return Err(error(
+                self.src(),
+                id,
+                format!(
+                    "`{}` is defined in terms of itself ({})",
+                    id.name,
+                    cycle.join(" -> ")
+                ),
+            ));
+        }
+
+        self.stack.push(id.name.clone());
+        let referenced_from = std::mem::replace(&mut self.file, file);
+        let value = match binding {
+            // bare names alias whatever they refer to
+            BindingValue::Number(Expr::Var(alias)) => self.resolve(&alias)?,
+            BindingValue::Number(e) => Value::Number(self.number(e)?),
+            BindingValue::Operations(ops) => Value::Operations(self.operations(ops)?),
+        };
+        self.file = referenced_from;
+        self.stack.pop();
+
+        self.resolved.insert(id.name.clone(), value.clone());
+        Ok(value)
+    }
+
+    // Substitute bound names into an expression, folding it down to a
+    // single number if it doesn't depend on time
+    fn number(&mut self, e: Expr) -> Result<Expr, Box<ParseError>> {
+        let e = match e {
+            Expr::Var(id) | Expr::Param(id) => match self.resolve(&id)? {
+                Value::Number(n) => n,
+                v => {
+                    return Err(error(
+                        self.src(),
+                        &id,
+                        format!("expected a number but `{}` is {}", id.name, v.describe()),
+                    ));
+                }
+            },
+            Expr::Neg(e) => Expr::Neg(Box::new(self.number(*e)?)),
+            Expr::BinOp(l, op, r) => {
+                Expr::BinOp(Box::new(self.number(*l)?), op, Box::new(self.number(*r)?))
+            }
+            Expr::Call(f, args) => Expr::Call(
+                f,
+                args.into_iter()
+                    .map(|a| self.number(a))
+                    .collect::<Result<_, _>>()?,
+            ),
+            e => e,
+        };
+
+        Ok(match e.is_animated() {
+            true => e,
+            false => Expr::Num(e.value()),
+        })
+    }
+
+    fn operations(&mut self, ops: Vec<Operation>) -> Result<Vec<Operation>, Box<ParseError>> {
+        let mut expanded = Vec::with_capacity(ops.len());
+        for mut op in ops {
+            if let Operation::Ref(id) = &op {
+                match self.resolve(id)? {
+                    Value::Operations(group) => expanded.extend(group),
+                    v => {
+                        return Err(error(
+                            self.src(),
+                            id,
+                            format!(
+                                "expected an operation but `{}` is {}",
+                                id.name,
+                                v.describe()
+                            ),
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            for e in op.exprs_mut() {
+                *e = self.number(e.clone())?;
+            }
+            if let Operation::CopyTo(targets) = &mut op {
+                for target in targets.iter_mut() {
+                    target.operations =
+                        self.destination_operations(std::mem::take(&mut target.operations))?;
+                }
+            }
+            expanded.push(op);
+        }
+
+        Ok(expanded)
+    }
+
+    // Operations for a single `copy_to` destination. The grammar only
+    // allows those that apply per destination, but named groups can hold
+    // anything.
+    fn destination_operations(
+        &mut self,
+        ops: Vec<Operation>,
+    ) -> Result<Vec<Operation>, Box<ParseError>> {
+        let mut expanded = Vec::with_capacity(ops.len());
+        for op in ops {
+            let reference = match &op {
+                Operation::Ref(id) => Some(id.clone()),
+                _ => None,
+            };
+            let group = self.operations(Vec::from([op]))?;
+            if let Some(id) = reference
+                && let Some(o) = group.iter().find(|o| !o.per_destination())
+            {
+                return Err(error(
+                    self.src(),
+                    &id,
+                    format!(
+                        "`{}` can't be used on a copy_to destination, as it includes `{}`",
+                        id.name,
+                        o.keyword()
+                    ),
+                ));
+            }
+            expanded.extend(group);
+        }
+
+        Ok(expanded)
+    }
+}
+
+fn error(file: &SourceFile, id: &Ident, message: String) -> Box<ParseError> {
+    let mut e = ParseError::new(&file.src, id.span.start, id.span.end, message)
+        .in_file(file.name.as_deref());
+    e.token = Some(id.name.clone());
+    Box::new(e)
+}
@@ -0,0 +1,788 @@
+use super::ast::{
+    Adjustment, BinOp, Binding, BindingValue, BlendMode, Cmp, Condition, CopyTarget, Expr, FaceIdx,
+    FacePart, FaceRef, FlipVariant, FrameShape, Length, Metric, MetricKind, Operation,
+    PixelateMode, Selector, Shape, Statement, Transform, Window,
+};
+use std::fmt::{self, Display, Write};
+
+const INDENT: &str = "  ";
+
+// Canonical source for parsed statements, which parses back into the same
+// statements. Comments aren't part of the tree, so they are dropped.
+pub fn format(statements: &[Statement]) -> String {
+    let mut f = Formatter::default();
+    let mut transforms = Vec::new();
+    for s in statements {
+        match s {
+            Statement::Transform(t) => transforms.push(t),
+            s => {
+                f.transforms(&std::mem::take(&mut transforms));
+                f.statement(s);
+            }
+        }
+    }
+    f.transforms(&transforms);
+    f.out
+}
+
+// Whether source has comments, which formatting it would drop. Strings
+// (`lut` paths) can hold anything but a quote or a line break, so are
+// skipped over.
+pub fn has_comments(src: &str) -> bool {
+    let mut rest = src;
+    while let Some(i) = rest.find(['"', '/']) {
+        let after = &rest[i + 1..];
+        if rest[i..].starts_with('"') {
+            rest = match after.find(['"', '\n']) {
+                Some(end) => &after[end + 1..],
+                None => "",
+            };
+        } else if after.starts_with(['/', '*']) {
+            return true;
+        } else {
+            rest = after;
+        }
+    }
+    false
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    depth: usize,
+    // blocks are separated from whatever follows by a blank line
+    after_block: bool,
+}
+
+impl Formatter {
+    fn statement(&mut self, s: &Statement) {
+        match s {
+            Statement::Transform(t) => self.transforms(&[t]),
+            Statement::Let(b) => self.line(b),
+            Statement::Param(p) => self.line(format_args!("param {} = {}", p.name.name, p.default)),
+            Statement::Include(inc) => self.line(format_args!("include \"{}\"", inc.path)),
+            Statement::Scene(scene) => self.block(format_args!("at {}", scene.window), |f| {
+                f.transforms(&scene.transforms.iter().collect::<Vec<_>>())
+            }),
+            Statement::Loop(_) => self.line("loop"),
+        }
+    }
+
+    // Consecutive ordered transforms share an `ordered` block
+    fn transforms(&mut self, transforms: &[&Transform]) {
+        for group in transforms.chunk_by(|a, b| a.ordered == b.ordered) {
+            match group[0].ordered {
+                false => self.conditional(group),
+                true => self.block("ordered", |f| f.conditional(group)),
+            }
+        }
+    }
+
+    // Consecutive transforms with the same condition share a `when` block
+    fn conditional(&mut self, transforms: &[&Transform]) {
+        for group in transforms.chunk_by(|a, b| a.condition == b.condition) {
+            match &group[0].condition {
+                None => group.iter().for_each(|t| self.line(t)),
+                Some(c) => self.block(format_args!("when {c}"), |f| {
+                    group.iter().for_each(|t| f.line(t))
+                }),
+            }
+        }
+    }
+
+    fn line(&mut self, line: impl Display) {
+        if std::mem::take(&mut self.after_block) {
+            self.out.push('\n');
+        }
+        let _ = writeln!(self.out, "{}{line}", INDENT.repeat(self.depth));
+    }
+
+    fn block(&mut self, header: impl Display, body: impl FnOnce(&mut Self)) {
+        if !self.out.is_empty() && !self.out.ends_with("{\n") {
+            self.after_block = true;
+        }
+        self.line(format_args!("{header} {{"));
+        self.depth += 1;
+        body(self);
+        self.depth -= 1;
+        self.after_block = false;
+        self.line("}");
+        self.after_block = true;
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "let {} = {}", self.name.name, self.value)
+    }
+}
+
+impl Display for BindingValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingValue::Number(e) => write!(f, "{e}"),
+            BindingValue::Operations(ops) => write_list(f, ops),
+        }
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}s..", self.start)?;
+        match self.end {
+            Some(end) => write!(f, "{end}s"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.shape)?;
+        if !self.operations.is_empty() {
+            write!(f, " ")?;
+            write_list(f, &self.operations)?;
+        }
+        Ok(())
+    }
+}
+
+// Conditions are written with the shorthand they were parsed from where
+// possible, eg. `blink(leye)` rather than `eye_open(leye) < 0.2`
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Condition::is_open(self.metric.clone()) {
+            return write!(f, "{}", self.metric);
+        }
+        if *self == Condition::blink(self.metric.face.clone()) {
+            return write!(f, "blink({})", self.metric.face);
+        }
+
+        let cmp = match self.cmp {
+            Cmp::Gt => ">",
+            Cmp::Lt => "<",
+        };
+        write!(f, "{} {cmp} {}", self.metric, self.threshold)
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.kind {
+            MetricKind::MouthOpen => "mouth_open",
+            MetricKind::EyeOpen => "eye_open",
+        };
+        write!(f, "{name}({})", self.face)
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Shape::FaceRef(fr) => write!(f, "{fr}"),
+            Shape::Frame(fs) => write!(f, "{fs}"),
+            Shape::Union(shapes) => {
+                for (i, s) in shapes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{s}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for FrameShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameShape::Rect(x, y, w, h) => write!(f, "rect({x}, {y}, {w}, {h})"),
+            FrameShape::Poly(points) => {
+                write!(f, "poly(")?;
+                for (i, (x, y)) in points.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "({x}, {y})")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Length::Px(px) => write!(f, "{px}"),
+            Length::Percent(pct) => write!(f, "{pct}%"),
+        }
+    }
+}
+
+impl Display for FaceRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.part)?;
+        if let Some(fi) = &self.face_idx {
+            write!(f, "{fi}")?;
+        }
+        for a in &self.adjustments {
+            write!(f, ".{a}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for FaceIdx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaceIdx::Absolute(i) => write!(f, "#{i}"),
+            FaceIdx::Relative(i) if *i < 0 => write!(f, "-{}", i.unsigned_abs()),
+            FaceIdx::Relative(i) => write!(f, "+{i}"),
+            FaceIdx::Select(s) => write!(f, "@{s}"),
+        }
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::Largest => write!(f, "largest"),
+            Selector::Smallest => write!(f, "smallest"),
+            Selector::Leftmost => write!(f, "leftmost"),
+            Selector::Rightmost => write!(f, "rightmost"),
+            Selector::Nearest(x, y) => write!(f, "nearest({x}, {y})"),
+        }
+    }
+}
+
+impl Display for FacePart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.keyword())
+    }
+}
+
+impl Display for Adjustment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Adjustment::Offset(x, y) => write!(f, "offset({x}, {y})"),
+            Adjustment::Grow(mag) => write!(f, "grow({mag})"),
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Tile => write!(f, "tile"),
+            Operation::Scale(e) => write!(f, "scale({e})"),
+            Operation::Rotate(e) => write!(f, "rotate({e})"),
+            Operation::CopyTo(targets) => {
+                write!(f, "copy_to(")?;
+                write_list(f, targets)?;
+                write!(f, ")")
+            }
+            Operation::SwapWith(s) => write!(f, "swap_with({s})"),
+            Operation::Cycle { shapes, period } => {
+                write!(f, "cycle(")?;
+                write_list(f, shapes)?;
+                match period {
+                    Some(period) => write!(f, ", period={period}s)"),
+                    None => write!(f, ")"),
+                }
+            }
+            Operation::Translate(x, y) => write!(f, "translate({x}, {y})"),
+            Operation::Flip(fv) => write!(f, "flip({fv})"),
+            Operation::Drift(velocity, angle) => write!(f, "drift({velocity}, {angle})"),
+            Operation::Spin(velocity) => write!(f, "spin({velocity})"),
+            Operation::Brightness(e) => write!(f, "brighten({e})"),
+            Operation::Saturation(e) => write!(f, "saturate({e})"),
+            Operation::Chans(r, g, b) => write!(f, "channels({r}, {g}, {b})"),
+            Operation::Reshape(dxl, dxr, dyt, dyb) => {
+                write!(f, "reshape({dxl}, {dxr}, {dyt}, {dyb})")
+            }
+            Operation::Blur(radius) => write!(f, "blur({radius})"),
+            Operation::Pixelate(size, PixelateMode::Grid) => write!(f, "pixelate({size})"),
+            Operation::Pixelate(size, mode) => write!(f, "pixelate({size}, {mode})"),
+            Operation::Hue(degrees) => write!(f, "hue({degrees})"),
+            Operation::Contrast(e) => write!(f, "contrast({e})"),
+            Operation::Gamma(e) => write!(f, "gamma({e})"),
+            Operation::Invert => write!(f, "invert"),
+            Operation::Grayscale => write!(f, "grayscale"),
+            Operation::Sepia(e) => write!(f, "sepia({e})"),
+            Operation::Lut { path, strength } if *strength == Expr::Num(1.) => {
+                write!(f, "lut(\"{path}\")")
+            }
+            Operation::Lut { path, strength } => write!(f, "lut(\"{path}\", {strength})"),
+            Operation::Opacity(e) => write!(f, "opacity({e})"),
+            Operation::Blend(mode) => write!(f, "blend({mode})"),
+            Operation::Ref(id) => write!(f, "{}", id.name),
+        }
+    }
+}
+
+impl Display for CopyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.shape)?;
+        if !self.operations.is_empty() {
+            write!(f, " {{ ")?;
+            write_list(f, &self.operations)?;
+            write!(f, " }}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for FlipVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlipVariant::Both => write!(f, "both"),
+            FlipVariant::Vertical => write!(f, "vertical"),
+            FlipVariant::Horizontal => write!(f, "horizontal"),
+        }
+    }
+}
+
+impl Display for PixelateMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PixelateMode::Grid => write!(f, "grid"),
+            PixelateMode::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+impl Display for BlendMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlendMode::Normal => write!(f, "normal"),
+            BlendMode::Multiply => write!(f, "multiply"),
+            BlendMode::Screen => write!(f, "screen"),
+            BlendMode::Overlay => write!(f, "overlay"),
+            BlendMode::Add => write!(f, "add"),
+            BlendMode::Difference => write!(f, "difference"),
+        }
+    }
+}
+
+// Expressions are written with the fewest parentheses that keep them
+// parsing the same way
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{n}"),
+            Expr::Var(id) | Expr::Param(id) => write!(f, "{}", id.name),
+            Expr::Time => write!(f, "t"),
+            Expr::Neg(e) => {
+                write!(f, "-")?;
+                write_operand(f, e, UNARY)
+            }
+            Expr::BinOp(l, op, r) => {
+                let prec = precedence(self);
+                write_operand(f, l, prec)?;
+                let op = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Rem => "%",
+                };
+                write!(f, " {op} ")?;
+                // operators are left associative, so a right operand at the
+                // same precedence needs parentheses
+                write_operand(f, r, prec + 1)
+            }
+            Expr::Call(func, args) => {
+                write!(f, "{}(", func.keyword())?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+const UNARY: u8 = 2;
+
+fn precedence(e: &Expr) -> u8 {
+    match e {
+        Expr::BinOp(_, BinOp::Add | BinOp::Sub, _) => 0,
+        Expr::BinOp(_, BinOp::Mul | BinOp::Div | BinOp::Rem, _) => 1,
+        // negative literals are written with a leading `-`
+        Expr::Neg(_) => UNARY,
+        Expr::Num(n) if n.is_sign_negative() => UNARY,
+        _ => 3,
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, e: &Expr, min_precedence: u8) -> fmt::Result {
+    match precedence(e) < min_precedence {
+        true => write!(f, "({e})"),
+        false => write!(f, "{e}"),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, items: &[impl Display]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{item}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::ast::{Func, Ident, Include, Param, Scene, Span};
+    use crate::lang::parse_ast;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_format() {
+        let src = "let pop=scale( 1.3 ),saturate(1.3)
+            mouth : pop , copy_to(leye+1,rect(10 10 5% 5%)), rotate(-(t*2 - 1) * 3)
+            when blink(leye) { face: flip(both) }
+            when mouth_open(face) < 0.2 { nose: tile }
+            when mouth_open(face) < 0.2 { leye: tile }
+            at 0s..500ms { face#0.above(0.5): spin }
+            loop";
+
+        assert_eq!(
+            format(&parse_ast(src).unwrap()),
+            "let pop = scale(1.3), saturate(1.3)
+mouth: pop, copy_to(leye+1, rect(10, 10, 5%, 5%)), rotate(-(t * 2 - 1) * 3)
+
+when blink(leye) {
+  face: flip(both)
+}
+
+when mouth_open(face) < 0.2 {
+  nose: tile
+  leye: tile
+}
+
+at 0s..0.5s {
+  face#0.offset(0, -0.5): spin(0.5)
+}
+
+loop
+"
+        );
+    }
+
+    #[test]
+    fn test_has_comments() {
+        assert!(!has_comments(
+            "face: spin(t / 2)\nmouth: lut(\"a//b.cube\")"
+        ));
+        assert!(has_comments("face: spin // spin\n"));
+        assert!(has_comments("face: /* big */ scale(2)"));
+        assert!(has_comments("mouth: lut(\"a.cube\") // graded"));
+    }
+
+    #[test]
+    fn test_examples_round_trip() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let src = std::fs::read_to_string(&path).unwrap();
+            let parsed = parse_ast(&src).unwrap();
+            let formatted = format(&parsed);
+            assert_eq!(
+                without_spans(parse_ast(&formatted).unwrap()),
+                without_spans(parsed),
+                "{}",
+                path.display()
+            );
+            // formatting is idempotent
+            assert_eq!(format(&parse_ast(&formatted).unwrap()), formatted);
+        }
+    }
+
+    fn without_spans(statements: Vec<Statement>) -> Vec<Statement> {
+        statements
+            .into_iter()
+            .map(Statement::without_spans)
+            .collect()
+    }
+
+    fn ident() -> impl Strategy<Value = Ident> {
+        prop::sample::select(Vec::from(["pop", "speed", "wobble", "x2", "big_eye"])).prop_map(
+            |name| Ident {
+                name: name.to_string(),
+                span: Span::default(),
+            },
+        )
+    }
+
+    fn num() -> impl Strategy<Value = f32> {
+        0f32..1000.
+    }
+
+    fn expr() -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            num().prop_map(Expr::Num),
+            num().prop_map(|n| Expr::Num(-n)),
+            ident().prop_map(Expr::Var),
+            Just(Expr::Time),
+        ];
+        leaf.prop_recursive(4, 24, 3, |inner| {
+            prop_oneof![
+                // negative literals parse as numbers, not negations
+                inner
+                    .clone()
+                    .prop_filter("negated literal", |e| !matches!(e, Expr::Num(_)))
+                    .prop_map(|e| Expr::Neg(Box::new(e))),
+                (
+                    inner.clone(),
+                    prop::sample::select(Vec::from([
+                        BinOp::Add,
+                        BinOp::Sub,
+                        BinOp::Mul,
+                        BinOp::Div,
+                        BinOp::Rem
+                    ])),
+                    inner.clone()
+                )
+                    .prop_map(|(l, op, r)| Expr::BinOp(
+                        Box::new(l),
+                        op,
+                        Box::new(r)
+                    )),
+                inner
+                    .clone()
+                    .prop_map(|x| Expr::Call(Func::Sin, Vec::from([x]))),
+                inner
+                    .clone()
+                    .prop_map(|x| Expr::Call(Func::Cos, Vec::from([x]))),
+                (inner.clone(), inner.clone(), inner)
+                    .prop_map(|(a, b, x)| Expr::Call(Func::Lerp, Vec::from([a, b, x]))),
+            ]
+        })
+    }
+
+    // translate rejects fractional literals
+    fn pixel_expr() -> impl Strategy<Value = Expr> {
+        expr().prop_map(|e| match e {
+            Expr::Num(n) => Expr::Num(n.round()),
+            e => e,
+        })
+    }
+
+    fn face_ref(adjusted: bool) -> impl Strategy<Value = FaceRef> {
+        let adjustment = prop_oneof![
+            (num(), num(), any::<bool>())
+                .prop_map(|(x, y, neg)| Adjustment::Offset(x, if neg { -y } else { y })),
+            num().prop_map(Adjustment::Grow),
+        ];
+        (
+            prop::sample::select(FacePart::ALL.to_vec()),
+            prop_oneof![
+                Just(None),
+                any::<u32>().prop_map(|i| Some(FaceIdx::Absolute(i))),
+                (-100i32..100).prop_map(|i| Some(FaceIdx::Relative(i))),
+                prop::sample::select(Vec::from([
+                    Selector::Largest,
+                    Selector::Smallest,
+                    Selector::Leftmost,
+                    Selector::Rightmost,
+                ]))
+                .prop_map(|s| Some(FaceIdx::Select(s))),
+                (num(), num()).prop_map(|(x, y)| Some(FaceIdx::Select(Selector::Nearest(x, y)))),
+            ],
+            prop::collection::vec(adjustment, 0..if adjusted { 3 } else { 1 }),
+        )
+            .prop_map(|(part, face_idx, adjustments)| FaceRef {
+                part,
+                face_idx,
+                adjustments,
+            })
+    }
+
+    fn shape() -> impl Strategy<Value = Shape> {
+        prop_oneof![
+            3 => member(),
+            1 => prop::collection::vec(member(), 2..4).prop_map(Shape::Union),
+        ]
+    }
+
+    // Shape that isn't a union
+    fn member() -> impl Strategy<Value = Shape> {
+        let length = prop_oneof![
+            any::<u32>().prop_map(Length::Px),
+            num().prop_map(Length::Percent),
+        ];
+        prop_oneof![
+            face_ref(true).prop_map(Shape::FaceRef),
+            [
+                length.clone(),
+                length.clone(),
+                length.clone(),
+                length.clone()
+            ]
+            .prop_map(|[x, y, w, h]| Shape::Frame(FrameShape::Rect(x, y, w, h))),
+            prop::collection::vec((length.clone(), length), 3..6)
+                .prop_map(|points| Shape::Frame(FrameShape::Poly(points))),
+        ]
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        let target = (shape(), prop::collection::vec(per_destination(), 0..3))
+            .prop_map(|(shape, operations)| CopyTarget { shape, operations });
+        prop_oneof![
+            Just(Operation::Tile),
+            prop::collection::vec(target, 0..3).prop_map(Operation::CopyTo),
+            shape().prop_map(Operation::SwapWith),
+            (
+                prop::collection::vec(shape(), 1..4),
+                prop::option::of(1u32..100_000)
+            )
+                .prop_map(|(shapes, period)| Operation::Cycle {
+                    shapes,
+                    period: period.map(|ms| ms as f32 / 1000.),
+                }),
+            (
+                prop::sample::select(Vec::from(["film.cube", "luts/teal orange.cube"])),
+                expr()
+            )
+                .prop_map(|(path, strength)| Operation::Lut {
+                    path: path.to_string(),
+                    strength,
+                }),
+            per_destination(),
+        ]
+    }
+
+    fn per_destination() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            expr().prop_map(Operation::Scale),
+            expr().prop_map(Operation::Rotate),
+            (pixel_expr(), pixel_expr()).prop_map(|(x, y)| Operation::Translate(x, y)),
+            prop::sample::select(Vec::from([
+                FlipVariant::Both,
+                FlipVariant::Vertical,
+                FlipVariant::Horizontal
+            ]))
+            .prop_map(Operation::Flip),
+            (expr(), expr()).prop_map(|(v, a)| Operation::Drift(v, a)),
+            expr().prop_map(Operation::Spin),
+            expr().prop_map(Operation::Brightness),
+            expr().prop_map(Operation::Saturation),
+            (expr(), expr(), expr()).prop_map(|(r, g, b)| Operation::Chans(r, g, b)),
+            [expr(), expr(), expr(), expr()]
+                .prop_map(|[a, b, c, d]| Operation::Reshape(a, b, c, d)),
+            expr().prop_map(Operation::Blur),
+            (
+                expr(),
+                prop::sample::select(Vec::from([PixelateMode::Grid, PixelateMode::Hex]))
+            )
+                .prop_map(|(size, mode)| Operation::Pixelate(size, mode)),
+            expr().prop_map(Operation::Hue),
+            expr().prop_map(Operation::Contrast),
+            expr().prop_map(Operation::Gamma),
+            Just(Operation::Invert),
+            Just(Operation::Grayscale),
+            expr().prop_map(Operation::Sepia),
+            expr().prop_map(Operation::Opacity),
+            prop::sample::select(Vec::from([
+                BlendMode::Normal,
+                BlendMode::Multiply,
+                BlendMode::Screen,
+                BlendMode::Overlay,
+                BlendMode::Add,
+                BlendMode::Difference
+            ]))
+            .prop_map(Operation::Blend),
+            ident().prop_map(Operation::Ref),
+        ]
+    }
+
+    fn condition() -> impl Strategy<Value = Condition> {
+        (
+            prop::sample::select(Vec::from([MetricKind::MouthOpen, MetricKind::EyeOpen])),
+            face_ref(false),
+            prop_oneof![
+                num(),
+                Just(Condition::MOUTH_OPEN),
+                Just(Condition::EYE_OPEN)
+            ],
+            prop::sample::select(Vec::from([Cmp::Gt, Cmp::Lt])),
+        )
+            .prop_map(|(kind, face, threshold, cmp)| Condition {
+                metric: Metric { kind, face },
+                cmp,
+                threshold,
+            })
+    }
+
+    fn transform() -> impl Strategy<Value = Transform> {
+        (
+            shape(),
+            prop::collection::vec(operation(), 0..4),
+            prop::option::of(condition()),
+            any::<bool>(),
+        )
+            .prop_map(|(shape, operations, condition, ordered)| Transform {
+                shape,
+                operations,
+                condition,
+                ordered,
+                span: Span::default(),
+            })
+    }
+
+    fn statement() -> impl Strategy<Value = Statement> {
+        let window = (0u32..100_000, prop::option::of(1u32..100_000)).prop_map(|(start, len)| {
+            let start = start as f32 / 1000.;
+            Window {
+                start,
+                end: len.map(|len| start + len as f32 / 1000.),
+            }
+        });
+        // a single operation reference is indistinguishable from a number
+        let ops = prop::collection::vec(operation(), 1..4).prop_filter("lone reference", |ops| {
+            !matches!(ops[..], [Operation::Ref(_)])
+        });
+
+        prop_oneof![
+            transform().prop_map(Statement::Transform),
+            (ident(), expr()).prop_map(|(name, e)| Statement::Let(Binding {
+                name,
+                value: BindingValue::Number(e),
+            })),
+            (ident(), ops).prop_map(|(name, ops)| Statement::Let(Binding {
+                name,
+                value: BindingValue::Operations(ops),
+            })),
+            (ident(), num(), any::<bool>()).prop_map(|(name, n, neg)| Statement::Param(Param {
+                name,
+                default: if neg { -n } else { n },
+            })),
+            "[a-z/._]{1,12}".prop_map(|path| Statement::Include(Include {
+                path,
+                span: Span::default(),
+            })),
+            (window, prop::collection::vec(transform(), 0..4)).prop_map(|(window, transforms)| {
+                Statement::Scene(Scene {
+                    window,
+                    transforms,
+                    span: Span::default(),
+                })
+            }),
+            Just(Statement::Loop(Span::default())),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(statements in prop::collection::vec(statement(), 0..6)) {
+            let formatted = format(&statements);
+            let parsed = parse_ast(&formatted).map_err(|e| {
+                TestCaseError::fail(format!("{}\n{formatted}", e.render()))
+            })?;
+            prop_assert_eq!(without_spans(parsed), statements, "{}", formatted);
+        }
+    }
+}
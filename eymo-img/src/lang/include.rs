@@ -0,0 +1,208 @@
+use super::ast::{BindingValue, Operation, Statement};
+use super::error::ParseError;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+// Loads the source of `include`d files
+pub trait Resolver {
+    // `path` has already been resolved relative to the including file
+    fn load(&mut self, path: &str) -> Result<String, String>;
+}
+
+// Reads included files from disk
+#[derive(Debug, Default)]
+pub struct FsResolver;
+
+impl Resolver for FsResolver {
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        std::fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+}
+
+// Preloaded sources, keyed by path
+impl Resolver for HashMap<String, String> {
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| "no such file".to_string())
+    }
+}
+
+// Configs that don't support includes
+#[derive(Debug, Default)]
+pub struct NoIncludes;
+
+impl Resolver for NoIncludes {
+    fn load(&mut self, _path: &str) -> Result<String, String> {
+        Err("includes are not supported here".to_string())
+    }
+}
+
+// Loaded files, and statements tagged with the index of the file they're from
+pub(crate) type Loaded = (Vec<SourceFile>, Vec<(usize, Statement)>);
+
+#[derive(Debug)]
+pub(crate) struct SourceFile {
+    // None for a root config that didn't come from a file
+    pub name: Option<String>,
+    pub src: String,
+}
+
+// Parse a config along with everything it includes. Included statements
+// are spliced in where the `include` was written.
+pub(crate) fn load(
+    mut root: SourceFile,
+    resolver: &mut impl Resolver,
+) -> Result<Loaded, Box<ParseError>> {
+    root.name = root.name.map(|n| relative_to(&n, None));
+    let mut loader = Loader {
+        resolver,
+        files: Vec::new(),
+        stack: Vec::new(),
+        statements: Vec::new(),
+    };
+    loader.stack.extend(root.name.clone());
+    loader.load(root)?;
+
+    Ok((loader.files, loader.statements))
+}
+
+struct Loader<'a, R> {
+    resolver: &'a mut R,
+    files: Vec<SourceFile>,
+    // files currently being loaded, for cycle detection
+    stack: Vec<String>,
+    statements: Vec<(usize, Statement)>,
+}
+
+impl<R: Resolver> Loader<'_, R> {
+    fn load(&mut self, file: SourceFile) -> Result<(), Box<ParseError>> {
+        let statements =
+            super::parse_ast(&file.src).map_err(|e| e.in_file(file.name.as_deref()))?;
+        let idx = self.files.len();
+        self.files.push(file);
+
+        for mut s in statements {
+            let inc = match s {
+                Statement::Include(inc) => inc,
+                _ => {
+                    resolve_luts(&mut s, self.files[idx].name.as_deref());
+                    self.statements.push((idx, s));
+                    continue;
+                }
+            };
+
+            let from = &self.files[idx];
+            let error = |message: String| {
+                Box::new(
+                    ParseError::new(&from.src, inc.span.start, inc.span.end, message)
+                        .in_file(from.name.as_deref()),
+                )
+            };
+
+            let path = relative_to(&inc.path, from.name.as_deref());
+            if let Some(pos) = self.stack.iter().position(|p| *p == path) {
+                let mut cycle = self.stack[pos..].to_vec();
+                cycle.push(path.clone());
+                return Err(error(format!(
+                    "`{path}` includes itself ({})",
+                    cycle.join(" -> ")
+                )));
+            }
+
+            // files are only included once, so a shared snippet library
+            // doesn't redefine its bindings
+            if self.files.iter().any(|f| f.name.as_ref() == Some(&path)) {
+                continue;
+            }
+
+            let src = self
+                .resolver
+                .load(&path)
+                .map_err(|e| error(format!("failed to include `{path}`: {e}")))?;
+
+            self.stack.push(path.clone());
+            self.load(SourceFile {
+                name: Some(path),
+                src,
+            })?;
+            self.stack.pop();
+        }
+
+        Ok(())
+    }
+}
+
+// `lut` files are found relative to the file using them, like includes
+fn resolve_luts(s: &mut Statement, from: Option<&str>) {
+    fn resolve(ops: &mut [Operation], from: Option<&str>) {
+        for o in ops {
+            if let Operation::Lut { path, .. } = o {
+                *path = relative_to(path, from);
+            }
+        }
+    }
+
+    match s {
+        Statement::Transform(t) => resolve(&mut t.operations, from),
+        Statement::Scene(scene) => {
+            for t in &mut scene.transforms {
+                resolve(&mut t.operations, from);
+            }
+        }
+        Statement::Let(b) => {
+            if let BindingValue::Operations(ops) = &mut b.value {
+                resolve(ops, from);
+            }
+        }
+        Statement::Param(_) | Statement::Include(_) | Statement::Loop(_) => {}
+    }
+}
+
+// Path of an included file relative to the directory of the file that
+// includes it, with `.` and `..` folded away so the same file always gets
+// the same name
+fn relative_to(path: &str, from: Option<&str>) -> String {
+    let joined = match from.and_then(|f| Path::new(f).parent()) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+
+    let mut normalized = PathBuf::new();
+    for c in joined.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(".."),
+            },
+            c => normalized.push(c),
+        }
+    }
+
+    normalized.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_to() {
+        assert_eq!(relative_to("a.eymo", None), "a.eymo");
+        assert_eq!(relative_to("a.eymo", Some("main.eymo")), "a.eymo");
+        assert_eq!(relative_to("a.eymo", Some("lib/main.eymo")), "lib/a.eymo");
+        assert_eq!(relative_to("../a.eymo", Some("lib/main.eymo")), "a.eymo");
+        assert_eq!(
+            relative_to("./b/a.eymo", Some("lib/main.eymo")),
+            "lib/b/a.eymo"
+        );
+        assert_eq!(relative_to("../a.eymo", Some("main.eymo")), "../a.eymo");
+        assert_eq!(
+            relative_to("/abs/a.eymo", Some("lib/main.eymo")),
+            "/abs/a.eymo"
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use super::ast::{FaceIdx, FaceRef, FrameShape, Operation, Shape, Statement, Transform};
+use super::error::ParseError;
+use super::include::SourceFile;
+use crate::shapes::rect::Rect;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // the config runs, but not quite as written
+    Warning,
+    // part of the config can never have any effect
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+// Problem with a config that parses, but won't run as written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    // message and location of the problem
+    pub diagnostic: ParseError,
+}
+
+impl Finding {
+    // Render with the offending source line, the same way as parse errors
+    pub fn render(&self) -> String {
+        self.diagnostic.render_as(&self.severity.to_string())
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.diagnostic)
+    }
+}
+
+// What the config will be run against. Checks needing anything left unset
+// are skipped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LintOptions {
+    // (width, height) of the frames, for checking rects and polys fit
+    pub frame: Option<(u32, u32)>,
+    // most faces detected at once, for checking absolute face indices
+    pub max_faces: Option<u32>,
+}
+
+pub(crate) fn lint(
+    files: &[SourceFile],
+    statements: &[(usize, Statement)],
+    options: &LintOptions,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (file, s) in statements {
+        let transforms = match s {
+            Statement::Transform(t) => std::slice::from_ref(t),
+            Statement::Scene(scene) => &scene.transforms[..],
+            _ => continue,
+        };
+
+        let file = &files[*file];
+        for t in transforms {
+            findings.extend(
+                lint_transform(t, options)
+                    .into_iter()
+                    .map(|(severity, message)| Finding {
+                        severity,
+                        diagnostic: ParseError::new(&file.src, t.span.start, t.span.end, message)
+                            .in_file(file.name.as_deref()),
+                    }),
+            );
+        }
+    }
+
+    findings
+}
+
+pub(crate) fn lint_transform(t: &Transform, options: &LintOptions) -> Vec<(Severity, String)> {
+    let mut found = Vec::new();
+    let ops = &t.operations;
+    let position = |keyword: &str| ops.iter().position(|o| o.keyword() == keyword);
+    let last_position = |keyword: &str| ops.iter().rposition(|o| o.keyword() == keyword);
+
+    // tiles are drawn straight from the source shape across the whole frame
+    if ops.contains(&Operation::Tile) {
+        for o in ops {
+            if matches!(
+                o,
+                Operation::Scale(_)
+                    | Operation::Rotate(_)
+                    | Operation::Translate(..)
+                    | Operation::Spin(_)
+                    | Operation::Drift(..)
+                    | Operation::Reshape(..)
+                    | Operation::CopyTo(_)
+                    | Operation::SwapWith(_)
+                    | Operation::Cycle { .. }
+            ) {
+                found.push((
+                    Severity::Error,
+                    format!("`{}` has no effect on tiled shapes", o.keyword()),
+                ));
+            }
+        }
+    }
+
+    // everything besides copies, swaps and cycles sets a single property of the
+    // transform, so only the last one counts, unless an ordered transform
+    // is composing geometric operations
+    for (i, o) in ops.iter().enumerate() {
+        let composed = t.ordered
+            && matches!(
+                o,
+                Operation::Scale(_)
+                    | Operation::Rotate(_)
+                    | Operation::Translate(..)
+                    | Operation::Flip(_)
+            );
+        let repeatable = composed
+            || o.is_colour()
+            || matches!(
+                o,
+                Operation::CopyTo(_) | Operation::SwapWith(_) | Operation::Cycle { .. }
+            );
+        if !repeatable && position(o.keyword()) != Some(i) && last_position(o.keyword()) == Some(i)
+        {
+            found.push((
+                Severity::Warning,
+                format!(
+                    "`{}` is used more than once, only the last one applies",
+                    o.keyword()
+                ),
+            ));
+        }
+    }
+
+    // spin and drift start over from no rotation or translation
+    let overrides = match t.ordered {
+        true => Vec::new(),
+        false => Vec::from([("rotate", "spin"), ("translate", "drift")]),
+    };
+    for (overridden, by) in overrides {
+        match (position(overridden), last_position(by)) {
+            (Some(o), Some(b)) if o < b => found.push((
+                Severity::Warning,
+                format!("`{overridden}` is overridden by the `{by}` after it"),
+            )),
+            _ => {}
+        }
+    }
+
+    if let Some(max) = options.max_faces {
+        for fr in face_refs(t) {
+            match fr.face_idx {
+                Some(FaceIdx::Absolute(idx)) if idx >= max => found.push((
+                    Severity::Error,
+                    format!(
+                        "`{fr}` can never match when at most {max} {} detected",
+                        if max == 1 { "face is" } else { "faces are" }
+                    ),
+                )),
+                _ => {}
+            }
+        }
+    }
+
+    if let Some((width, height)) = options.frame {
+        for fs in frame_shapes(t) {
+            let r = Rect::from(fs.resolve((width, height)));
+            if r.left() >= width || r.top() >= height {
+                found.push((
+                    Severity::Error,
+                    format!("`{fs}` is entirely outside the {width}x{height} frame"),
+                ));
+            } else if r.right() > width || r.bottom() > height {
+                found.push((
+                    Severity::Warning,
+                    format!("`{fs}` extends past the {width}x{height} frame"),
+                ));
+            }
+        }
+    }
+
+    found
+}
+
+// Every shape a transform reads from or writes to, with unions split
+// into their members
+fn shapes(t: &Transform) -> impl Iterator<Item = &Shape> {
+    std::iter::once(&t.shape)
+        .chain(t.operations.iter().flat_map(|o| match o {
+            Operation::CopyTo(targets) => targets.iter().map(|t| &t.shape).collect(),
+            Operation::SwapWith(s) => Vec::from([s]),
+            Operation::Cycle { shapes, .. } => shapes.iter().collect(),
+            _ => Vec::new(),
+        }))
+        .flat_map(|s| s.members())
+}
+
+pub(crate) fn face_refs(t: &Transform) -> Vec<&FaceRef> {
+    shapes(t)
+        .filter_map(|s| match s {
+            Shape::FaceRef(fr) => Some(fr),
+            Shape::Frame(_) | Shape::Union(_) => None,
+        })
+        .chain(t.condition.iter().map(|c| &c.metric.face))
+        .collect()
+}
+
+fn frame_shapes(t: &Transform) -> Vec<&FrameShape> {
+    shapes(t)
+        .filter_map(|s| match s {
+            Shape::Frame(fs) => Some(fs),
+            Shape::FaceRef(_) | Shape::Union(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{NoIncludes, lint as lint_source};
+
+    fn messages(src: &str, options: LintOptions) -> Vec<(Severity, String)> {
+        lint_source(src, &mut NoIncludes, &options)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.severity, f.diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_tile_conflicts() {
+        assert_eq!(
+            messages(
+                "face: tile, flip(both), scale(2), copy_to(mouth)",
+                LintOptions::default()
+            ),
+            [
+                (
+                    Severity::Error,
+                    "`scale` has no effect on tiled shapes".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "`copy_to` has no effect on tiled shapes".to_string()
+                ),
+            ]
+        );
+        // ordering doesn't help, only flips compose on tiles
+        assert_eq!(
+            messages(
+                "ordered {\n  face: tile, flip(both), rotate(45), flip(vertical)\n}",
+                LintOptions::default()
+            ),
+            [(
+                Severity::Error,
+                "`rotate` has no effect on tiled shapes".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_duplicates_and_overrides() {
+        let src = "let pop = scale(1.3), brighten(1.2)
+            mouth: pop, scale(2), copy_to(leye), copy_to(reye)
+            leye: rotate(45), spin
+            reye: spin, rotate(45), drift, translate(10, 0)";
+        assert_eq!(
+            messages(src, LintOptions::default()),
+            [
+                (
+                    Severity::Warning,
+                    "`scale` is used more than once, only the last one applies".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "`rotate` is overridden by the `spin` after it".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ordered_composes() {
+        let src = "ordered { leye: rotate(45), spin, scale(2), scale(0.5), drift, drift }";
+        assert_eq!(
+            messages(src, LintOptions::default()),
+            [(
+                Severity::Warning,
+                "`drift` is used more than once, only the last one applies".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_colour_composes() {
+        let src = "face: invert, hue(90), invert, gamma(2), gamma(1.5)\nmouth: gamma(2), sepia(1)";
+        assert!(messages(src, LintOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_face_indices() {
+        let src = "mouth#1: swap_with(mouth#2)\nwhen mouth_open(face#5) { leye#0: tile }";
+        assert!(messages(src, LintOptions::default()).is_empty());
+
+        let options = LintOptions {
+            max_faces: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            messages(src, options),
+            [
+                (
+                    Severity::Error,
+                    "`mouth#2` can never match when at most 2 faces are detected".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "`face#5` can never match when at most 2 faces are detected".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_union_members() {
+        let options = LintOptions {
+            max_faces: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            messages("leye#3|reye: copy_to(mouth@largest|nose#2)", options),
+            [
+                (
+                    Severity::Error,
+                    "`leye#3` can never match when at most 2 faces are detected".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "`nose#2` can never match when at most 2 faces are detected".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_shapes() {
+        let src = "rect(10, 10, 50, 50): copy_to(rect(600, 400, 100, 10), rect(90%, 0, 20%, 10))
+            poly((0, 0), (700, 0), (0, 10)): tile";
+        let options = LintOptions {
+            frame: Some((640, 480)),
+            ..Default::default()
+        };
+        assert_eq!(
+            messages(src, options),
+            [
+                (
+                    Severity::Warning,
+                    "`rect(600, 400, 100, 10)` extends past the 640x480 frame".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "`rect(90%, 0, 20%, 10)` extends past the 640x480 frame".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "`poly((0, 0), (700, 0), (0, 10))` extends past the 640x480 frame".to_string()
+                ),
+            ]
+        );
+
+        let src = "rect(640, 0, 10, 10): tile";
+        assert_eq!(
+            messages(src, options),
+            [(
+                Severity::Error,
+                "`rect(640, 0, 10, 10)` is entirely outside the 640x480 frame".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_finding_location() {
+        let mut files = std::collections::HashMap::from([(
+            "lib.eymo".to_string(),
+            "let x = 1\nmouth: scale(2), scale(3)".to_string(),
+        )]);
+        let findings =
+            lint_source("include \"lib.eymo\"", &mut files, &LintOptions::default()).unwrap();
+        assert_eq!(findings.len(), 1);
+
+        let d = &findings[0].diagnostic;
+        assert_eq!(d.file.as_deref(), Some("lib.eymo"));
+        assert_eq!((d.line, d.column), (2, 1));
+        assert!(findings[0].render().starts_with("warning: `scale`"));
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+
+// Transforms that did nothing, or less than written, on the last frame run
+// in strict mode
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    // position of the transform among all transforms in the config
+    pub index: usize,
+    // the transform as written, eg. `mouth#2: scale(2)`
+    pub statement: String,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    // a face reference, eg. `mouth#2`, matched no detected face
+    MissingFace(String),
+    // a `lut` operation's file hasn't been loaded onto the GPU
+    MissingLut(String),
+    // an operation is ignored or overridden, as described
+    Unsupported(String),
+    // the frame ran out of time before the transform, as described
+    Timeout(String),
+}
+
+impl Reason {
+    // Short name of the kind of reason, eg. for grouping in a UI
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Reason::MissingFace(_) => "missing_face",
+            Reason::MissingLut(_) => "missing_lut",
+            Reason::Unsupported(_) => "unsupported",
+            Reason::Timeout(_) => "timeout",
+        }
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "transform {} `{}`: {}",
+            self.index, self.statement, self.reason
+        )
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::MissingFace(face) => write!(f, "no detected face matches `{face}`"),
+            Reason::MissingLut(path) => write!(f, "lut `{path}` isn't loaded"),
+            Reason::Unsupported(message) => write!(f, "{message}"),
+            Reason::Timeout(message) => write!(f, "timed out: {message}"),
+        }
+    }
+}
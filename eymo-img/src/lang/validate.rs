@@ -0,0 +1,168 @@
+use super::ast::{BindingValue, Expr, FrameShape, Operation, Shape, Statement, Transform};
+use super::error::ParseError;
+use super::include::SourceFile;
+
+// Checks the grammar can't make on its own, shared by source and JSON
+// configs so both reject the same things.
+// Runs before expansion, as folding constants evaluates expressions.
+pub(crate) fn validate(
+    files: &[SourceFile],
+    statements: &[(usize, Statement)],
+) -> Result<(), Box<ParseError>> {
+    for (file, s) in statements {
+        let file = &files[*file];
+        match s {
+            Statement::Transform(t) => check(file, t, transform(t))?,
+            Statement::Let(b) => {
+                let result = match &b.value {
+                    BindingValue::Number(e) => expr(e),
+                    BindingValue::Operations(ops) => operations(ops),
+                };
+                let span = b.name.span;
+                result.map_err(|message| error(file, span.start, span.end, message))?;
+            }
+            Statement::Scene(scene) => {
+                if scene
+                    .window
+                    .end
+                    .is_some_and(|end| end <= scene.window.start)
+                {
+                    let span = scene.span;
+                    return Err(error(
+                        file,
+                        span.start,
+                        span.end,
+                        "scene must end after it starts".to_string(),
+                    ));
+                }
+                for t in &scene.transforms {
+                    check(file, t, transform(t))?;
+                }
+            }
+            Statement::Param(_) | Statement::Include(_) | Statement::Loop(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Checks that need `let` bindings substituted in, run after expansion
+pub(crate) fn validate_expanded(
+    files: &[SourceFile],
+    statements: &[(usize, Statement)],
+) -> Result<(), Box<ParseError>> {
+    for (file, s) in statements {
+        let transforms = match s {
+            Statement::Transform(t) => std::slice::from_ref(t),
+            Statement::Scene(scene) => &scene.transforms[..],
+            _ => continue,
+        };
+        for t in transforms {
+            check(&files[*file], t, whole_pixels(&t.operations))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check(
+    file: &SourceFile,
+    t: &Transform,
+    result: Result<(), String>,
+) -> Result<(), Box<ParseError>> {
+    result.map_err(|message| error(file, t.span.start, t.span.end, message))
+}
+
+fn transform(t: &Transform) -> Result<(), String> {
+    shape(&t.shape)?;
+    operations(&t.operations)
+}
+
+fn operations(ops: &[Operation]) -> Result<(), String> {
+    for op in ops {
+        for e in op.exprs() {
+            expr(e)?;
+        }
+        match op {
+            Operation::CopyTo(targets) => {
+                for target in targets {
+                    shape(&target.shape)?;
+                    operations(&target.operations)?;
+                }
+            }
+            Operation::SwapWith(s) => shape(s)?,
+            Operation::Cycle { shapes, period } => {
+                if shapes.is_empty() {
+                    return Err("cycle needs at least one shape".to_string());
+                }
+                if period.is_some_and(|p| p <= 0.) {
+                    return Err("cycle period must be longer than 0s".to_string());
+                }
+                shapes.iter().try_for_each(shape)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn shape(s: &Shape) -> Result<(), String> {
+    for member in s.members() {
+        if let Shape::Frame(FrameShape::Poly(points)) = member
+            && points.len() < 3
+        {
+            return Err("poly needs at least 3 points".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+fn expr(e: &Expr) -> Result<(), String> {
+    match e {
+        Expr::Neg(e) => expr(e),
+        Expr::BinOp(l, _, r) => expr(l).and(expr(r)),
+        Expr::Call(f, args) => {
+            if args.len() != f.arity() {
+                return Err(format!(
+                    "{} takes {} argument{}, not {}",
+                    f.keyword(),
+                    f.arity(),
+                    if f.arity() == 1 { "" } else { "s" },
+                    args.len()
+                ));
+            }
+            args.iter().try_for_each(expr)
+        }
+        Expr::Num(_) | Expr::Var(_) | Expr::Param(_) | Expr::Time => Ok(()),
+    }
+}
+
+// Translation moves whole pixels. Constant offsets must be whole, animated
+// ones are rounded to the nearest pixel every frame.
+fn whole_pixels(ops: &[Operation]) -> Result<(), String> {
+    for op in ops {
+        match op {
+            Operation::Translate(x, y)
+                if [x, y]
+                    .iter()
+                    .any(|e| matches!(e, Expr::Num(n) if n.fract() != 0.)) =>
+            {
+                return Err("translate only accepts whole pixels".to_string());
+            }
+            Operation::CopyTo(targets) => {
+                for target in targets {
+                    whole_pixels(&target.operations)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn error(file: &SourceFile, start: usize, end: usize, message: String) -> Box<ParseError> {
+    Box::new(ParseError::new(&file.src, start, end, message).in_file(file.name.as_deref()))
+}
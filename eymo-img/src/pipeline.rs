@@ -27,6 +27,13 @@ pub struct Face {
     pub r_eye_region: Polygon,
     pub bound: Rect,
     pub forehead: Polygon,
+    // Mouth aspect ratio, lip gap over mouth width. ~0 when closed
+    pub mouth_open: f32,
+    // Eye aspect ratios, lid gap over eye width. Drops below ~0.2 in a blink
+    pub l_eye_open: f32,
+    pub r_eye_open: f32,
+    // Head tilt in radians, from the angle between the eyes
+    pub rot: f32,
 }
 
 pub type Detection = Vec<Face>;
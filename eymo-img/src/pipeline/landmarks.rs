@@ -50,6 +50,13 @@ const FOREHEAD_IDXS: [usize; 14] = [
     54, 68, 107, 9, 336, 298, 284, 332, 297, 338, 10, 109, 67, 103,
 ];
 
+// Inner lip top, bottom, left and right corners
+const MOUTH_OPEN_IDXS: [usize; 4] = [13, 14, 78, 308];
+
+// Upper and lower lid pairs, then the eye's corners
+const L_EYE_OPEN_IDXS: [usize; 6] = [160, 144, 158, 153, 33, 133];
+const R_EYE_OPEN_IDXS: [usize; 6] = [385, 380, 387, 373, 362, 263];
+
 const MODEL: &[u8; 1435541] = include_bytes!("./face_landmark.tar.gz");
 
 impl FaceLandmarker {
@@ -246,6 +253,7 @@ fn extract_results(
 
     Ok(Face {
         bound: run_bounds,
+        rot: -run_rot,
         face: extract_feature(
             r, &FACE_IDXS, x_offset, y_offset, x_scale, y_scale, &origin, run_rot,
         ),
@@ -314,9 +322,50 @@ fn extract_results(
         )
         .stretch([1., 1., 2.2, 0.7])
         .to_owned(),
+        mouth_open: mouth_aspect_ratio(r, x_scale, y_scale),
+        l_eye_open: eye_aspect_ratio(r, &L_EYE_OPEN_IDXS, x_scale, y_scale),
+        r_eye_open: eye_aspect_ratio(r, &R_EYE_OPEN_IDXS, x_scale, y_scale),
     })
 }
 
+// Position of a mesh keypoint in frame pixels, relative to the crop. The
+// crop is stretched to the model's square input, so distances are only
+// meaningful once each axis is scaled back. Turning the crop back upright
+// is left out, as rotating doesn't change distances between points once
+// both axes are in pixels.
+fn kpt_pos(mesh: &[f32], i: usize, x_scale: f32, y_scale: f32) -> (f32, f32) {
+    (mesh[i * 3] * x_scale, mesh[i * 3 + 1] * y_scale)
+}
+
+fn kpt_dist(mesh: &[f32], a: usize, b: usize, x_scale: f32, y_scale: f32) -> f32 {
+    let (ax, ay) = kpt_pos(mesh, a, x_scale, y_scale);
+    let (bx, by) = kpt_pos(mesh, b, x_scale, y_scale);
+    (ax - bx).hypot(ay - by)
+}
+
+// Height of the inner lips over their width
+fn mouth_aspect_ratio(mesh: &[f32], x_scale: f32, y_scale: f32) -> f32 {
+    let [top, bottom, left, right] = MOUTH_OPEN_IDXS;
+    let width = kpt_dist(mesh, left, right, x_scale, y_scale);
+    if width == 0. {
+        return 0.;
+    }
+
+    kpt_dist(mesh, top, bottom, x_scale, y_scale) / width
+}
+
+// Average height between the lids over the eye's width
+fn eye_aspect_ratio(mesh: &[f32], idxs: &[usize; 6], x_scale: f32, y_scale: f32) -> f32 {
+    let [t1, b1, t2, b2, l, r] = *idxs;
+    let width = kpt_dist(mesh, l, r, x_scale, y_scale);
+    if width == 0. {
+        return 0.;
+    }
+
+    (kpt_dist(mesh, t1, b1, x_scale, y_scale) + kpt_dist(mesh, t2, b2, x_scale, y_scale))
+        / (2. * width)
+}
+
 fn extract_feature(
     mesh: &[f32],
     kpt_idxs: &[usize],
@@ -343,3 +392,58 @@ fn extract_feature(
 
     Polygon::new(points)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mesh with only the given keypoints placed, at model input coordinates
+    fn mesh(kpts: &[(usize, [f32; 2])]) -> Vec<f32> {
+        let mut mesh = vec![0.; 468 * 3];
+        for &(i, [x, y]) in kpts {
+            mesh[i * 3] = x;
+            mesh[i * 3 + 1] = y;
+        }
+        mesh
+    }
+
+    fn mouth(gap: f32) -> Vec<f32> {
+        let [top, bottom, left, right] = MOUTH_OPEN_IDXS;
+        mesh(&[
+            (top, [96., 130.]),
+            (bottom, [96., 130. + gap]),
+            (left, [76., 132.]),
+            (right, [116., 132.]),
+        ])
+    }
+
+    fn eye(idxs: &[usize; 6], gap: f32) -> Vec<f32> {
+        let [t1, b1, t2, b2, l, r] = *idxs;
+        mesh(&[
+            (t1, [64., 80.]),
+            (b1, [64., 80. + gap]),
+            (t2, [72., 80.]),
+            (b2, [72., 80. + gap]),
+            (l, [58., 82.]),
+            (r, [78., 82.]),
+        ])
+    }
+
+    #[test]
+    fn mouth_open_and_closed() {
+        assert_eq!(mouth_aspect_ratio(&mouth(20.), 1., 1.), 0.5);
+        assert_eq!(mouth_aspect_ratio(&mouth(0.), 1., 1.), 0.);
+        // a crop twice as wide as it is tall is squashed horizontally to
+        // fit the model, so the mouth is wider in the frame than the mesh
+        assert_eq!(mouth_aspect_ratio(&mouth(20.), 2., 1.), 0.25);
+    }
+
+    #[test]
+    fn eyes_open_and_closed() {
+        for idxs in [&L_EYE_OPEN_IDXS, &R_EYE_OPEN_IDXS] {
+            assert_eq!(eye_aspect_ratio(&eye(idxs, 6.), idxs, 1., 1.), 0.3);
+            assert_eq!(eye_aspect_ratio(&eye(idxs, 0.), idxs, 1., 1.), 0.);
+            assert_eq!(eye_aspect_ratio(&eye(idxs, 6.), idxs, 1., 2.), 0.6);
+        }
+    }
+}
@@ -72,6 +72,60 @@ impl Polygon {
 
         self
     }
+
+    // Width and height measured along axes rotated by `theta` radians, eg.
+    // the size of a face part ignoring head tilt
+    pub fn oriented_size(&self, theta: f32) -> (f32, f32) {
+        let (sin, cos) = theta.sin_cos();
+        let (xs, ys): (Vec<f32>, Vec<f32>) = self
+            .points
+            .iter()
+            .map(|p| {
+                let (x, y) = (p.x as f32, p.y as f32);
+                (x * cos + y * sin, y * cos - x * sin)
+            })
+            .unzip();
+
+        let extent = |v: &[f32]| {
+            v.iter().copied().fold(f32::MIN, f32::max) - v.iter().copied().fold(f32::MAX, f32::min)
+        };
+        (extent(&xs), extent(&ys))
+    }
+
+    // Move every point by (dx, dy), stopping at the top left of the frame
+    pub fn shift(&mut self, dx: f32, dy: f32) -> &mut Self {
+        let points = self
+            .points
+            .iter()
+            .map(|p| (p.x as f32 + dx, p.y as f32 + dy))
+            .collect();
+        self.place(points)
+    }
+
+    // Scale every point's distance from the center by `mag`
+    pub fn grow(&mut self, mag: f32) -> &mut Self {
+        let center = self.center();
+        let (cx, cy) = (center.x as f32, center.y as f32);
+        let points = self
+            .points
+            .iter()
+            .map(|p| (cx + (p.x as f32 - cx) * mag, cy + (p.y as f32 - cy) * mag))
+            .collect();
+        self.place(points)
+    }
+
+    // Move points to new positions, moving the polygon as a whole back onto
+    // the frame if any would be past its top or left, so it keeps its shape
+    fn place(&mut self, points: Vec<(f32, f32)>) -> &mut Self {
+        let min_x = points.iter().map(|&(x, _)| x).fold(0., f32::min);
+        let min_y = points.iter().map(|&(_, y)| y).fold(0., f32::min);
+        for (p, (x, y)) in self.points.iter_mut().zip(points) {
+            p.x = (x - min_x).round() as u32;
+            p.y = (y - min_y).round() as u32;
+        }
+
+        self
+    }
 }
 
 impl From<Polygon> for Rect {
@@ -93,3 +147,29 @@ fn test_rounded_div() {
     assert_eq!(rounded_div(5, 4), 1);
     assert_eq!(rounded_div(20, 4), 5);
 }
+
+#[test]
+fn test_oriented_size() {
+    let poly = Polygon::from(Rect::from_tl(10, 10, 40, 20));
+    assert_eq!(poly.oriented_size(0.), (40., 20.));
+
+    // a quarter turn swaps width and height
+    let (w, h) = poly.oriented_size(std::f32::consts::FRAC_PI_2);
+    assert!((w - 20.).abs() < 0.01 && (h - 40.).abs() < 0.01, "{w} {h}");
+}
+
+#[test]
+fn test_shift_and_grow() {
+    let mut poly = Polygon::from(Rect::from_tl(10, 10, 20, 20));
+    poly.shift(5., -15.);
+    // stopped at the top of the frame, without squashing the shape
+    assert_eq!(Rect::from(poly), Rect::from_tl(15, 0, 20, 20));
+
+    let mut poly = Polygon::from(Rect::from_tl(10, 10, 20, 20));
+    poly.grow(1.5);
+    assert_eq!(Rect::from(poly), Rect::from_tl(5, 5, 30, 30));
+
+    let mut poly = Polygon::from(Rect::from_tl(2, 10, 20, 20));
+    poly.grow(2.);
+    assert_eq!(Rect::from(poly), Rect::from_tl(0, 0, 40, 40));
+}
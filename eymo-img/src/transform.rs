@@ -1,49 +1,171 @@
+use crate::imggpu::lut::{Cube, Lut};
 use crate::imggpu::vertex::Vertex;
 use crate::shapes::point::Point;
 use crate::shapes::shape::Shape;
 use crate::{imggpu::gpu::GpuExecutor, shapes::rect::Rect};
+use colour::{ColourEntry, ColourStage};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ops::Range;
 use tracing::{Level, span, warn};
 use web_time::Instant;
 use wgpu::util::DeviceExt;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+mod colour;
+
+pub use colour::ColourStep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FlipVariant {
     Vertical,
     Horizontal,
     Both,
 }
 
-#[derive(Debug)]
-pub struct Transform {
+// Shape of the blocks a pixelated shape is drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelateMode {
+    Grid,
+    Hex,
+}
+
+// How a drawn shape combines with the pixels it's drawn over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+    Difference,
+}
+
+// A geometric operation of an ordered transform. Spin and drift stand for
+// the rotation and translation they have animated so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Translate(f32, f32),
+    Scale(f32),
+    Rotate(f32),
+    Flip(FlipVariant),
+    Spin,
+    Drift,
+}
+
+// What a transform does to each of its shapes
+#[derive(Debug, Clone)]
+pub struct Settings {
     rotate_deg: Option<f32>,
     flip: Option<FlipVariant>,
     translation: Option<(i32, i32)>,
     scale: f32,
     tile: bool,
     rps: Option<f32>,
-    last_tick: Instant,
     drift_vec: Option<(f32, f32)>,
     reshape: Option<[f32; 4]>,
+    // geometric operations to compose in order, replacing the fixed order
+    // of flip, translate, scale, rotate
+    steps: Option<Vec<Step>>,
     brightness_mod: f32,
     saturation_mod: f32,
     chans_mod: [f32; 4],
+    // gaussian blur radius in pixels, 0. for none
+    blur: f32,
+    // mosaic block size in source pixels
+    pixelate: Option<(f32, PixelateMode)>,
+    // colour operations, composed in order
+    colour: Vec<ColourStep>,
+    // path of the `.cube` lut to grade with, and how much of it to mix in
+    lut: Option<(String, f32)>,
+    // how much of the drawn shape shows over what's beneath it
+    opacity: f32,
+    blend: BlendMode,
+}
+
+#[derive(Debug)]
+pub struct Transform {
+    settings: Settings,
+    // settings for `copy_to` destinations with operations of their own,
+    // by destination index
+    destinations: HashMap<usize, Settings>,
+    last_tick: Instant,
     cache: HashMap<String, ShapeOpState>,
     gpu_gunk: GpuGunk,
+    // whether the settings' lut was found missing, so it's only warned
+    // about once
+    missing_lut: bool,
 }
 
 #[derive(Debug)]
 pub struct ShapeOp {
-    id: String,
-    base: Shape,
-    swap: Option<Shape>,
-    dest: Option<Shape>,
+    pub(crate) id: String,
+    pub(crate) base: Shape,
+    pub(crate) swap: Option<Shape>,
+    pub(crate) dest: Option<Shape>,
+    // shapes the base's contents move along, and seconds between moves
+    pub(crate) cycle: Vec<Shape>,
+    pub(crate) period: Option<f32>,
+    // index of the `copy_to` destination this copies to
+    pub(crate) destination: Option<usize>,
+}
+
+// Colour adjustments for a vertex, so copies drawn in the same render
+// pass can be adjusted differently. Negative values leave colours as is.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Adjustments {
+    // brightness and saturation factors
+    levels: [f32; 2],
+    // red, green, blue and alpha factors
+    chans: [f32; 4],
+    // mosaic block size in source pixels, and 0. for a grid of squares or
+    // 1. for hexagons
+    pixelate: [f32; 2],
+    // index of the first entry of the colour stage in the colours buffer
+    colour: u32,
+    // how much of the lut's grade to mix in
+    lut_strength: f32,
+    // how much of the blended colour to mix over the destination
+    opacity: f32,
+}
+
+// Uniforms for one pass of the blur shader
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurParams {
+    direction: [f32; 2],
+    radius: f32,
+    _pad: f32,
 }
 
 #[derive(Debug)]
 struct GpuGunk {
     bg_layout: wgpu::BindGroupLayout,
-    render_pipeline: wgpu::RenderPipeline,
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    // render pipelines by blend mode, built the first time a mode is drawn
+    render_pipelines: HashMap<BlendMode, wgpu::RenderPipeline>,
+    lut_sampler: wgpu::Sampler,
+    // bound in place of a lut for transforms without one
+    no_lut: Lut,
+    blur_pipeline: wgpu::RenderPipeline,
+}
+
+// Everything drawn for a transform in one frame
+struct Draw<'a> {
+    vertices: Vec<Vertex>,
+    // one per vertex
+    adjustments: Vec<Adjustments>,
+    // colour stages of the settings drawn with, one after another
+    colours: Vec<ColourEntry>,
+    // vertices to draw with each blend mode, in order
+    blends: Vec<(BlendMode, Range<u32>)>,
+    // vertices to blur afterwards, with the blur radius
+    blurs: Vec<(f32, Range<u32>)>,
+    lut: Option<&'a Lut>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -51,6 +173,8 @@ struct ShapeOpState {
     translation: Option<(i32, i32)>,
     drift_vec: Option<(f32, f32)>,
     rotate_deg: Option<f32>,
+    // seconds since the op started
+    elapsed: f32,
 }
 
 impl ShapeOp {
@@ -60,15 +184,43 @@ impl ShapeOp {
             base: a.into(),
             swap: Some(b.into()),
             dest: None,
+            cycle: Vec::new(),
+            period: None,
+            destination: None,
+        }
+    }
+
+    pub fn cycle(
+        id: String,
+        base: impl Into<Shape>,
+        shapes: Vec<Shape>,
+        period: Option<f32>,
+    ) -> Self {
+        Self {
+            id,
+            base: base.into(),
+            swap: None,
+            dest: None,
+            cycle: shapes,
+            period,
+            destination: None,
         }
     }
 
-    pub fn copy(id: String, src: impl Into<Shape>, dest: impl Into<Shape>) -> Self {
+    pub fn copy(
+        id: String,
+        destination: usize,
+        src: impl Into<Shape>,
+        dest: impl Into<Shape>,
+    ) -> Self {
         Self {
             id,
             base: src.into(),
             dest: Some(dest.into()),
             swap: None,
+            cycle: Vec::new(),
+            period: None,
+            destination: Some(destination),
         }
     }
 
@@ -78,15 +230,150 @@ impl ShapeOp {
             base: s.into(),
             dest: None,
             swap: None,
+            cycle: Vec::new(),
+            period: None,
+            destination: None,
+        }
+    }
+}
+
+impl Adjustments {
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        2 => Float32x2,
+        3 => Float32x4,
+        4 => Float32x2,
+        5 => Uint32,
+        6 => Float32,
+        7 => Float32,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
         }
     }
 }
 
+// Wider blurs cost more samples per pixel than they're worth
+const MAX_BLUR_RADIUS: f32 = 64.;
+
 const TILE_HEIGHT: u32 = 100;
 const TILE_WIDTH: u32 = 160;
 
 impl Transform {
     pub fn new(gpu: &mut GpuExecutor) -> Self {
+        Self {
+            settings: Settings::default(),
+            destinations: HashMap::new(),
+            last_tick: Instant::now(),
+            cache: HashMap::new(),
+            gpu_gunk: GpuGunk::init(gpu),
+            missing_lut: false,
+        }
+    }
+
+    // Replace the settings for the transform's shapes, and for copies to
+    // destinations with their own operations
+    pub fn configure(&mut self, settings: Settings, destinations: HashMap<usize, Settings>) {
+        self.settings = settings;
+        self.destinations = destinations;
+        self.missing_lut = false;
+    }
+
+    // Forget animation state, so spin and drift start over
+    pub fn reset(&mut self) {
+        self.cache.clear();
+        self.last_tick = Instant::now();
+    }
+
+    pub fn execute(
+        &mut self,
+        gpu: &mut GpuExecutor,
+        tex: &wgpu::Texture,
+        shape_ops: Vec<ShapeOp>,
+    ) -> wgpu::Texture {
+        let span = span!(Level::DEBUG, "Transform#execute");
+        let _guard = span.enter();
+
+        let time_elapsed = self.last_tick.elapsed().as_secs_f32();
+        let mut vertices = Vec::new();
+        let mut adjustments = Vec::new();
+        let mut blurs = Vec::new();
+        let mut blends = Vec::new();
+        let mut colours = Vec::new();
+        // where each settings' colour stage starts, by destination
+        let mut colour_starts = HashMap::new();
+        let lut = self.lut(gpu);
+        for op in shape_ops.into_iter() {
+            let destination = op.destination.filter(|d| self.destinations.contains_key(d));
+            let settings = destination
+                .and_then(|d| self.destinations.get(&d))
+                .unwrap_or(&self.settings);
+            let colour = *colour_starts.entry(destination).or_insert_with(|| {
+                let start = colours.len() as u32;
+                colours.extend(ColourStage::new(&settings.colour).entries());
+                start
+            });
+            let prev_val = self.cache.remove(&op.id);
+            let next_cache_val = settings.tick(&op.base, tex, prev_val, time_elapsed);
+            let op_vertices = settings.gen_vertices(tex, &op, &next_cache_val);
+            let mut op_adjustments = settings.adjustments(colour);
+            if lut.is_none() {
+                op_adjustments.lut_strength = 0.;
+            }
+            adjustments.extend(std::iter::repeat_n(op_adjustments, op_vertices.len()));
+            blurs.push((settings.blur, op_vertices.len()));
+            blends.push((settings.blend, op_vertices.len()));
+            vertices.push(op_vertices);
+            self.cache.insert(op.id.clone(), next_cache_val);
+        }
+        self.last_tick = Instant::now();
+
+        let sampler = self.sampler(gpu);
+        let draw = Draw {
+            vertices: vertices.concat(),
+            adjustments,
+            colours,
+            blends: draw_ranges(&blends),
+            blurs: blur_ranges(&blurs),
+            lut: lut.as_ref(),
+        };
+        self.gpu_gunk.execute(gpu, draw, tex, sampler)
+    }
+
+    // The lut to grade with, if there is one and it's been loaded
+    fn lut(&mut self, gpu: &GpuExecutor) -> Option<Lut> {
+        let (path, _) = self.settings.lut.as_ref()?;
+        let lut = gpu.lut(path).cloned();
+        if lut.is_none() && !self.missing_lut {
+            warn!("Lut {path} isn't loaded. Skipping lut operation.");
+            self.missing_lut = true;
+        }
+        lut
+    }
+
+    fn sampler(&self, gpu: &GpuExecutor) -> wgpu::Sampler {
+        let address_mode = if self.settings.tile {
+            wgpu::AddressMode::Repeat
+        } else {
+            wgpu::AddressMode::ClampToEdge
+        };
+
+        gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
         Self {
             flip: None,
             scale: 1.,
@@ -94,17 +381,23 @@ impl Transform {
             brightness_mod: -1.,
             saturation_mod: -1.,
             chans_mod: [-1., -1., -1., -1.],
-            last_tick: Instant::now(),
             rotate_deg: None,
             rps: None,
             translation: None,
             drift_vec: None,
             reshape: None,
-            cache: HashMap::new(),
-            gpu_gunk: GpuGunk::init(gpu),
+            steps: None,
+            blur: 0.,
+            pixelate: None,
+            colour: Vec::new(),
+            lut: None,
+            opacity: 1.,
+            blend: BlendMode::Normal,
         }
     }
+}
 
+impl Settings {
     pub fn set_brightness(&mut self, b: f32) {
         self.brightness_mod = b;
     }
@@ -117,6 +410,36 @@ impl Transform {
         self.chans_mod = [r, g, b, 1.];
     }
 
+    // radius: pixels either side of each pixel blurred into it
+    pub fn set_blur(&mut self, radius: f32) {
+        self.blur = radius.clamp(0., MAX_BLUR_RADIUS);
+    }
+
+    // size: width of each block in pixels of the shape's source, so
+    // copies pixelate the same as the original whatever their size
+    pub fn set_pixelate(&mut self, size: f32, mode: PixelateMode) {
+        self.pixelate = Some((size.max(1.), mode));
+    }
+
+    // strength: 0. leaves colours as they are, 1. grades them fully
+    pub fn set_lut(&mut self, path: &str, strength: f32) {
+        self.lut = Some((path.to_string(), strength.clamp(0., 1.)));
+    }
+
+    // opacity: 0. leaves the destination as it is, 1. draws over it fully
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0., 1.);
+    }
+
+    pub fn set_blend(&mut self, mode: BlendMode) {
+        self.blend = mode;
+    }
+
+    // Apply colour `steps` after any already added
+    pub fn add_colour_steps(&mut self, steps: Vec<ColourStep>) {
+        self.colour.extend(steps);
+    }
+
     pub fn set_reshape(&mut self, dxl: f32, dxr: f32, dyt: f32, dyb: f32) {
         self.reshape = Some([dxl, dxr, dyt, dyb]);
     }
@@ -158,39 +481,43 @@ impl Transform {
         self.translate_by(0, 0); // initalize translation
     }
 
-    pub fn execute(
-        &mut self,
-        gpu: &mut GpuExecutor,
-        tex: &wgpu::Texture,
-        shape_ops: Vec<ShapeOp>,
-    ) -> wgpu::Texture {
-        let span = span!(Level::DEBUG, "Transform#execute");
-        let _guard = span.enter();
+    // Apply `steps` in order after any already added, each about the
+    // shape's original center, so eg. a rotate after a translate swings
+    // the shape around
+    pub fn add_steps(&mut self, steps: Vec<Step>) {
+        self.steps.get_or_insert_with(Vec::new).extend(steps);
+    }
 
-        let mut vertices = Vec::new();
-        for op in shape_ops.into_iter() {
-            let prev_val = self.cache.remove(&op.id);
-            let next_cache_val = self.tick(&op.base, tex, prev_val);
-            vertices.push(self.gen_vertices(tex, &op, &next_cache_val));
-            self.cache.insert(op.id.clone(), next_cache_val);
+    // `colour` is where the settings' colour stage starts in the colours
+    // buffer
+    fn adjustments(&self, colour: u32) -> Adjustments {
+        Adjustments {
+            levels: [self.brightness_mod, self.saturation_mod],
+            chans: self.chans_mod,
+            pixelate: match self.pixelate {
+                Some((size, PixelateMode::Grid)) => [size, 0.],
+                Some((size, PixelateMode::Hex)) => [size, 1.],
+                None => [-1., 0.],
+            },
+            colour,
+            lut_strength: self.lut.as_ref().map_or(0., |(_, strength)| *strength),
+            opacity: self.opacity,
         }
-        self.last_tick = Instant::now();
-
-        let sampler = self.sampler(gpu);
-        self.gpu_gunk.execute(
-            gpu,
-            vertices.concat(),
-            tex,
-            &[self.brightness_mod, self.saturation_mod],
-            &self.chans_mod,
-            sampler,
-        )
     }
 
-    fn tick(&self, shape: &Shape, tex: &wgpu::Texture, prev: Option<ShapeOpState>) -> ShapeOpState {
+    // `time_elapsed` is seconds since the previous tick
+    fn tick(
+        &self,
+        shape: &Shape,
+        tex: &wgpu::Texture,
+        prev: Option<ShapeOpState>,
+        time_elapsed: f32,
+    ) -> ShapeOpState {
         // animate spin and drift since last iteration
-        let mut next_state = ShapeOpState::default();
-        let time_elapsed = self.last_tick.elapsed().as_secs_f32();
+        let mut next_state = ShapeOpState {
+            elapsed: prev.as_ref().map_or(0., |p| p.elapsed + time_elapsed),
+            ..Default::default()
+        };
 
         let defaults = (
             self.rotate_deg.unwrap_or(0.),
@@ -198,7 +525,7 @@ impl Transform {
             self.translation.unwrap_or((0, 0)),
         );
 
-        let (rotate_deg, (vel, ang), (tx, ty)) = match prev {
+        let (rotate_deg, (_, ang), (tx, ty)) = match prev {
             Some(prev) => (
                 prev.rotate_deg.unwrap_or(defaults.0),
                 prev.drift_vec.unwrap_or(defaults.1),
@@ -206,6 +533,9 @@ impl Transform {
             ),
             None => defaults,
         };
+        // direction carries over between frames as the shape bounces, but
+        // velocity can be animated so always comes from the current settings
+        let vel = defaults.1.0;
 
         if self.rps.is_some() {
             let rps = self.rps.unwrap();
@@ -282,7 +612,18 @@ impl Transform {
             vertex_groups.push(self.vertices_for_shapes(tex, base, op.dest.as_ref().unwrap(), s));
         }
 
-        if op.swap.is_none() && op.dest.is_none() {
+        if !op.cycle.is_empty() {
+            let ring = std::iter::once(base)
+                .chain(op.cycle.iter())
+                .collect::<Vec<_>>();
+            let steps = cycle_steps(op.period, s.elapsed, ring.len());
+            for (i, src) in ring.iter().enumerate() {
+                let dest = ring[(i + steps) % ring.len()];
+                vertex_groups.push(self.vertices_for_shapes(tex, src, dest, s));
+            }
+        }
+
+        if op.swap.is_none() && op.dest.is_none() && op.cycle.is_empty() {
             vertex_groups.push(self.vertices_for_shapes(tex, base, base, s));
         }
 
@@ -388,6 +729,19 @@ impl Transform {
 
         let mut clip_center = Vertex::new(&[l + (r - l) / 2., b + (t - b) / 2.]);
 
+        // tiles can only be flipped, so of ordered steps only flips apply
+        // to them, composed in turn
+        let flip_variant = match &self.steps {
+            Some(steps) if !self.tile => {
+                return ordered_vertices(vertices, steps, s, clip_center.position, width, height);
+            }
+            Some(steps) => steps.iter().fold(self.flip, |f, step| match step {
+                Step::Flip(v) => compose_flips(f, *v),
+                _ => f,
+            }),
+            None => self.flip,
+        };
+
         // Texture bounds (for flip)
         let mut l = f32::MAX;
         let mut r = f32::MIN;
@@ -429,9 +783,7 @@ impl Transform {
         vertices
             .iter_mut()
             .map(|v| {
-                if self.flip.is_some() {
-                    let flip_variant = self.flip.unwrap();
-
+                if let Some(flip_variant) = flip_variant {
                     if flip_variant == FlipVariant::Both || flip_variant == FlipVariant::Horizontal
                     {
                         v.tex_coord[0] = flip(v.tex_coord[0], l, r);
@@ -471,55 +823,40 @@ impl Transform {
             })
             .collect::<Vec<_>>()
     }
-
-    fn sampler(&self, gpu: &GpuExecutor) -> wgpu::Sampler {
-        let address_mode = if self.tile {
-            wgpu::AddressMode::Repeat
-        } else {
-            wgpu::AddressMode::ClampToEdge
-        };
-
-        gpu.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: address_mode,
-            address_mode_v: address_mode,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        })
-    }
 }
 
 impl GpuGunk {
     fn execute(
         &mut self,
         gpu: &mut GpuExecutor,
-        vertices: Vec<Vertex>,
+        draw: Draw,
         tex: &wgpu::Texture,
-        adjs: &[f32; 2],
-        chans: &[f32; 4],
         sampler: wgpu::Sampler,
     ) -> wgpu::Texture {
-        let adjustments = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("adjustments"),
-            size: 8,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        gpu.queue
-            .write_buffer(&adjustments, 0, &bytemuck::cast_slice(adjs));
-
-        let chans_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("chans"),
-            size: 16,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        gpu.queue
-            .write_buffer(&chans_buf, 0, &bytemuck::cast_slice(chans));
+        let Draw {
+            vertices,
+            adjustments,
+            mut colours,
+            blends,
+            blurs,
+            lut,
+        } = draw;
+        for &(mode, _) in &blends {
+            self.load_render_pipeline(gpu, mode);
+        }
 
+        let lut = lut.unwrap_or(&self.no_lut);
+        // storage buffers can't be empty, even with nothing to draw
+        if colours.is_empty() {
+            colours.extend(ColourStage::new(&[]).entries());
+        }
+        let colours_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("colours_buffer"),
+                contents: bytemuck::cast_slice(&colours),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
         let render_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("render_bind_group2"),
             layout: &self.bg_layout,
@@ -536,11 +873,21 @@ impl GpuGunk {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: adjustments.as_entire_binding(),
+                    resource: wgpu::BindingResource::TextureView(
+                        &lut.texture.create_view(&Default::default()),
+                    ),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: chans_buf.as_entire_binding(),
+                    resource: wgpu::BindingResource::Sampler(&self.lut_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: lut.params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: colours_buffer.as_entire_binding(),
                 },
             ],
         });
@@ -571,6 +918,14 @@ impl GpuGunk {
                 usage: wgpu::BufferUsages::VERTEX,
             });
 
+        let adjustments_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("adjustments_buffer"),
+                contents: bytemuck::cast_slice(&adjustments),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -611,16 +966,183 @@ impl GpuGunk {
             ..Default::default()
         });
 
-        render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &render_bg, &[]);
         render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-        render_pass.draw(0..vertices.len() as u32, 0..1);
+        render_pass.set_vertex_buffer(1, adjustments_buffer.slice(..));
+        for (mode, range) in blends {
+            render_pass.set_pipeline(&self.render_pipelines[&mode]);
+            render_pass.draw(range, 0..1);
+        }
         drop(render_pass);
 
+        if !blurs.is_empty() {
+            self.blur(gpu, &mut encoder, &output_tex, &vertex_buffer, blurs);
+        }
+
         gpu.queue.submit(std::iter::once(encoder.finish()));
         output_tex
     }
 
+    // Blur the drawn shapes in `tex`, in two passes per radius: across the
+    // whole frame horizontally into a scratch texture, then vertically
+    // back into `tex`, drawing only the shapes' triangles so pixels
+    // outside them are left as is
+    fn blur(
+        &self,
+        gpu: &GpuExecutor,
+        encoder: &mut wgpu::CommandEncoder,
+        tex: &wgpu::Texture,
+        vertex_buffer: &wgpu::Buffer,
+        blurs: Vec<(f32, Range<u32>)>,
+    ) {
+        let scratch_tex = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("blur scratch tex"),
+            size: tex.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let tex_view = tex.create_view(&Default::default());
+        let scratch_view = scratch_tex.create_view(&Default::default());
+
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let full_coverage = Vertex::triangles_for_full_coverage();
+        let full_coverage_buffer =
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("blur_full_coverage_buffer"),
+                    contents: bytemuck::cast_slice(&full_coverage),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+
+        for (radius, range) in blurs {
+            let pass = |src: &wgpu::TextureView, direction: [f32; 2]| {
+                let params = gpu
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("blur_params"),
+                        contents: bytemuck::cast_slice(&[BlurParams {
+                            direction,
+                            radius,
+                            _pad: 0.,
+                        }]),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("blur_bind_group"),
+                    layout: &self.blur_pipeline.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(src),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: params.as_entire_binding(),
+                        },
+                    ],
+                })
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blur_horizontal_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &scratch_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(Default::default()),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+            render_pass.set_pipeline(&self.blur_pipeline);
+            render_pass.set_bind_group(0, &pass(&tex_view, [1., 0.]), &[]);
+            render_pass.set_vertex_buffer(0, full_coverage_buffer.slice(..));
+            render_pass.draw(0..full_coverage.len() as u32, 0..1);
+            drop(render_pass);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blur_vertical_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &tex_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load, // keep everything outside the shapes
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+            render_pass.set_pipeline(&self.blur_pipeline);
+            render_pass.set_bind_group(0, &pass(&scratch_view, [0., 1.]), &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.draw(range, 0..1);
+            drop(render_pass);
+        }
+    }
+
+    // Build the render pipeline for `mode` if it hasn't been already. The
+    // mode is an override constant of the shader, which blends with the
+    // destination itself, as fixed function blending can't do overlay or
+    // difference.
+    fn load_render_pipeline(&mut self, gpu: &GpuExecutor, mode: BlendMode) {
+        if self.render_pipelines.contains_key(&mode) {
+            return;
+        }
+
+        let constants = [("blend_mode", mode as u32 as f64)];
+        let render_pipeline = gpu
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("render_pipeline"),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: Some("vert_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[Vertex::desc(), Adjustments::desc()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    ..Default::default()
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: Some("frag_main"),
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        constants: &constants,
+                        ..Default::default()
+                    },
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: None,
+                cache: None,
+            });
+        self.render_pipelines.insert(mode, render_pipeline);
+    }
+
     fn init(gpu: &mut GpuExecutor) -> Self {
         let shader_code = wgpu::include_wgsl!("transform.wgsl");
         let shader = gpu.load_shader("transform", shader_code);
@@ -648,7 +1170,23 @@ impl GpuGunk {
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 2,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: Default::default(),
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
@@ -657,10 +1195,10 @@ impl GpuGunk {
                         count: None,
                     },
                     wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
                             has_dynamic_offset: false,
                             min_binding_size: None,
                         },
@@ -677,13 +1215,14 @@ impl GpuGunk {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = gpu
+        let blur_shader = gpu.load_shader("blur", wgpu::include_wgsl!("blur.wgsl"));
+        let blur_pipeline = gpu
             .device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("render_pipeline"),
-                layout: Some(&pipeline_layout),
+                label: Some("blur_pipeline"),
+                layout: None,
                 vertex: wgpu::VertexState {
-                    module: &shader,
+                    module: &blur_shader,
                     entry_point: Some("vert_main"),
                     compilation_options: Default::default(),
                     buffers: &[Vertex::desc()],
@@ -692,7 +1231,7 @@ impl GpuGunk {
                     ..Default::default()
                 },
                 fragment: Some(wgpu::FragmentState {
-                    module: &shader,
+                    module: &blur_shader,
                     entry_point: Some("frag_main"),
                     compilation_options: Default::default(),
                     targets: &[Some(wgpu::ColorTargetState {
@@ -707,11 +1246,112 @@ impl GpuGunk {
                 cache: None,
             });
 
+        let lut_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
         Self {
             bg_layout,
-            render_pipeline,
+            shader,
+            pipeline_layout,
+            render_pipelines: HashMap::new(),
+            lut_sampler,
+            no_lut: Cube::identity(2).load(gpu),
+            blur_pipeline,
+        }
+    }
+}
+
+// Apply `steps` to clip space vertices, about `pivot`. Steps are measured
+// in pixels, so rotations don't stretch in non-square frames.
+fn ordered_vertices(
+    vertices: &[Vertex],
+    steps: &[Step],
+    s: &ShapeOpState,
+    pivot: [f32; 2],
+    width: u32,
+    height: u32,
+) -> Vec<Vertex> {
+    let (w, h) = (width as f32, height as f32);
+    let to_px = |[x, y]: [f32; 2]| [(x + 1.) / 2. * w, (1. - y) / 2. * h];
+    let to_clip = |[x, y]: [f32; 2]| [x / w * 2. - 1., 1. - y / h * 2.];
+
+    let [px, py] = to_px(pivot);
+    let m = steps
+        .iter()
+        .fold(Affine::translate(-px, -py), |m, step| {
+            m.then(match *step {
+                Step::Translate(x, y) => Affine::translate(x, y),
+                Step::Scale(f) => Affine::scale(f),
+                Step::Rotate(deg) => Affine::rotate(deg),
+                Step::Flip(v) => Affine::flip(v),
+                Step::Spin => Affine::rotate(s.rotate_deg.unwrap_or(0.)),
+                Step::Drift => {
+                    let (x, y) = s.translation.unwrap_or((0, 0));
+                    Affine::translate(x as f32, y as f32)
+                }
+            })
+        })
+        .then(Affine::translate(px, py));
+
+    vertices
+        .iter()
+        .map(|v| Vertex::new_with_tex(&to_clip(m.apply(to_px(v.position))), &v.tex_coord))
+        .collect()
+}
+
+// 2D affine matrix in pixel space, `[a, b, c, d, tx, ty]` mapping (x, y)
+// to (ax + cy + tx, bx + dy + ty)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Affine([f32; 6]);
+
+impl Affine {
+    fn translate(x: f32, y: f32) -> Self {
+        Self([1., 0., 0., 1., x, y])
+    }
+
+    fn scale(f: f32) -> Self {
+        Self([f, 0., 0., f, 0., 0.])
+    }
+
+    // Counter-clockwise on screen (y points down), matching unordered
+    // rotation
+    fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self([cos, -sin, sin, cos, 0., 0.])
+    }
+
+    fn flip(v: FlipVariant) -> Self {
+        match v {
+            FlipVariant::Horizontal => Self([-1., 0., 0., 1., 0., 0.]),
+            FlipVariant::Vertical => Self([1., 0., 0., -1., 0., 0.]),
+            FlipVariant::Both => Self([-1., 0., 0., -1., 0., 0.]),
         }
     }
+
+    // This followed by `o`
+    fn then(self, o: Self) -> Self {
+        let [a, b, c, d, tx, ty] = self.0;
+        let [oa, ob, oc, od, otx, oty] = o.0;
+        Self([
+            oa * a + oc * b,
+            ob * a + od * b,
+            oa * c + oc * d,
+            ob * c + od * d,
+            oa * tx + oc * ty + otx,
+            ob * tx + od * ty + oty,
+        ])
+    }
+
+    fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [a, b, c, d, tx, ty] = self.0;
+        [a * x + c * y + tx, b * x + d * y + ty]
+    }
 }
 
 fn flip(val: f32, min: f32, max: f32) -> f32 {
@@ -720,6 +1360,62 @@ fn flip(val: f32, min: f32, max: f32) -> f32 {
     res.min(max).max(min)
 }
 
+// Flipping one way then another, where flipping the same way twice undoes
+// the flip
+fn compose_flips(a: Option<FlipVariant>, b: FlipVariant) -> Option<FlipVariant> {
+    let axes = |f| match f {
+        None => (false, false),
+        Some(FlipVariant::Horizontal) => (true, false),
+        Some(FlipVariant::Vertical) => (false, true),
+        Some(FlipVariant::Both) => (true, true),
+    };
+    let ((ah, av), (bh, bv)) = (axes(a), axes(Some(b)));
+    match (ah != bh, av != bv) {
+        (false, false) => None,
+        (true, false) => Some(FlipVariant::Horizontal),
+        (false, true) => Some(FlipVariant::Vertical),
+        (true, true) => Some(FlipVariant::Both),
+    }
+}
+
+// How far around a ring of `len` shapes contents have moved, one step to
+// start with and another every period
+fn cycle_steps(period: Option<f32>, elapsed: f32, len: usize) -> usize {
+    let periods = match period {
+        // saturates rather than overflowing, however short the period
+        Some(period) => (elapsed / period) as usize,
+        None => 0,
+    };
+    (periods % len + 1) % len
+}
+
+// Vertex ranges to draw alike, from a value and vertex count for each op
+// in draw order. Neighbouring ops with the same value share a range.
+fn draw_ranges<T: Copy + PartialEq>(ops: &[(T, usize)]) -> Vec<(T, Range<u32>)> {
+    let mut ranges: Vec<(T, Range<u32>)> = Vec::new();
+    let mut start = 0;
+    for &(value, count) in ops {
+        let end = start + count as u32;
+        match ranges.last_mut() {
+            Some((v, range)) if *v == value => range.end = end,
+            _ if count > 0 => ranges.push((value, start..end)),
+            _ => {}
+        }
+        start = end;
+    }
+    ranges
+}
+
+// Vertex ranges to blur after drawing, from the blur radius and vertex
+// count of each op in draw order. Neighbouring ops blurred by the same
+// radius share a range, so they're blurred together.
+fn blur_ranges(blurs: &[(f32, usize)]) -> Vec<(f32, Range<u32>)> {
+    draw_ranges(blurs)
+        .into_iter()
+        .filter(|(radius, _)| *radius > 0.)
+        .collect()
+}
+
 fn mirror_x(degrees: f32) -> f32 {
     360. - degrees
 }
@@ -731,3 +1427,163 @@ fn mirror_y(degrees: f32) -> f32 {
         180. - degrees
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 200;
+    const HEIGHT: u32 = 100;
+
+    // Pixel positions of a 20x20 square centered in the frame after `steps`
+    fn square_after(steps: &[Step], s: &ShapeOpState) -> Vec<[f32; 2]> {
+        let (w, h) = (WIDTH as f32, HEIGHT as f32);
+        let square = [[90., 40.], [110., 40.], [110., 60.], [90., 60.]]
+            .map(|[x, y]: [f32; 2]| Vertex::new(&[x / w * 2. - 1., 1. - y / h * 2.]));
+
+        ordered_vertices(&square, steps, s, [0., 0.], WIDTH, HEIGHT)
+            .iter()
+            .map(|v| {
+                let [x, y] = v.position;
+                [
+                    ((x + 1.) / 2. * w * 100.).round() / 100.,
+                    ((1. - y) / 2. * h * 100.).round() / 100.,
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steps_apply_in_order() {
+        let state = ShapeOpState::default();
+        assert_eq!(
+            square_after(&[Step::Rotate(90.), Step::Translate(100., 0.)], &state),
+            [[190., 60.], [190., 40.], [210., 40.], [210., 60.]]
+        );
+        // translating first swings the shape around its original center
+        assert_eq!(
+            square_after(&[Step::Translate(100., 0.), Step::Rotate(90.)], &state),
+            [[90., -40.], [90., -60.], [110., -60.], [110., -40.]]
+        );
+        assert_eq!(
+            square_after(&[Step::Translate(10., 0.), Step::Scale(2.)], &state),
+            [[100., 30.], [140., 30.], [140., 70.], [100., 70.]]
+        );
+        assert_eq!(
+            square_after(&[Step::Flip(FlipVariant::Horizontal)], &state),
+            [[110., 40.], [90., 40.], [90., 60.], [110., 60.]]
+        );
+    }
+
+    #[test]
+    fn repeated_steps_compose() {
+        let state = ShapeOpState::default();
+        assert_eq!(
+            square_after(&[Step::Scale(2.), Step::Scale(0.5)], &state),
+            square_after(&[], &state)
+        );
+        assert_eq!(
+            square_after(&[Step::Rotate(30.), Step::Rotate(60.)], &state),
+            square_after(&[Step::Rotate(90.)], &state)
+        );
+    }
+
+    #[test]
+    fn animated_steps() {
+        let state = ShapeOpState {
+            translation: Some((0, 20)),
+            drift_vec: None,
+            rotate_deg: Some(90.),
+            elapsed: 0.,
+        };
+        assert_eq!(
+            square_after(&[Step::Spin, Step::Translate(100., 0.)], &state),
+            square_after(
+                &[Step::Rotate(90.), Step::Translate(100., 0.)],
+                &ShapeOpState::default()
+            )
+        );
+        assert_eq!(
+            square_after(&[Step::Scale(2.), Step::Drift], &state),
+            [[80., 50.], [120., 50.], [120., 90.], [80., 90.]]
+        );
+    }
+
+    #[test]
+    fn ordered_flips_apply_to_tiles() {
+        let mut settings = Settings::default();
+        settings.set_tiling(true);
+        settings.add_steps(Vec::from([
+            Step::Flip(FlipVariant::Both),
+            Step::Rotate(45.),
+            Step::Flip(FlipVariant::Vertical),
+        ]));
+        let mut tile = Vec::from([
+            Vertex::new_with_tex(&[1., 1.], &[0.5, 0.]),
+            Vertex::new_with_tex(&[-1., 1.], &[0., 0.]),
+            Vertex::new_with_tex(&[-1., -1.], &[0., 0.5]),
+            Vertex::new_with_tex(&[1., -1.], &[0.5, 0.5]),
+        ]);
+        let flipped =
+            settings.scale_rotate_flip(&mut tile, WIDTH, HEIGHT, &ShapeOpState::default());
+        // flipped horizontally, with the vertical flips cancelling out and
+        // the tile left in place
+        assert_eq!(
+            flipped
+                .iter()
+                .map(|v| (v.position, v.tex_coord))
+                .collect::<Vec<_>>(),
+            [
+                ([1., 1.], [0., 0.]),
+                ([-1., 1.], [0.5, 0.]),
+                ([-1., -1.], [0.5, 0.5]),
+                ([1., -1.], [0., 0.5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn flips_compose() {
+        use FlipVariant::*;
+        assert_eq!(compose_flips(None, Both), Some(Both));
+        assert_eq!(compose_flips(Some(Both), Both), None);
+        assert_eq!(compose_flips(Some(Both), Vertical), Some(Horizontal));
+        assert_eq!(compose_flips(Some(Horizontal), Vertical), Some(Both));
+    }
+
+    #[test]
+    fn cycle_advances_each_period() {
+        assert_eq!(cycle_steps(None, 10., 3), 1);
+        assert_eq!(cycle_steps(Some(2.), 0., 3), 1);
+        assert_eq!(cycle_steps(Some(2.), 1.9, 3), 1);
+        assert_eq!(cycle_steps(Some(2.), 2., 3), 2);
+        assert_eq!(cycle_steps(Some(2.), 4., 3), 0);
+        assert_eq!(cycle_steps(Some(0.5), 2.2, 3), 2);
+        assert_eq!(cycle_steps(None, 0., 1), 0);
+        assert!(cycle_steps(Some(0.), 1., 3) < 3);
+        assert!(cycle_steps(Some(1e-30), 1e30, 3) < 3);
+    }
+
+    #[test]
+    fn blur_ranges_merge_neighbours() {
+        assert_eq!(blur_ranges(&[(0., 6), (0., 3)]), []);
+        assert_eq!(
+            blur_ranges(&[(12., 6), (12., 3), (0., 6), (12., 3), (4., 0)]),
+            [(12., 0..9), (12., 15..18)]
+        );
+        assert_eq!(blur_ranges(&[(4., 6), (12., 3)]), [(4., 0..6), (12., 6..9)]);
+    }
+
+    #[test]
+    fn draw_ranges_by_blend_mode() {
+        use BlendMode::*;
+        assert_eq!(
+            draw_ranges(&[(Normal, 6), (Multiply, 0), (Normal, 3), (Screen, 6)]),
+            [(Normal, 0..9), (Screen, 9..15)]
+        );
+        assert_eq!(
+            draw_ranges(&[(Add, 6), (Difference, 3), (Add, 3)]),
+            [(Add, 0..6), (Difference, 6..9), (Add, 9..12)]
+        );
+    }
+}
@@ -1,10 +1,31 @@
 @group(0) @binding(0) var input_tex : texture_2d<f32>;
 @group(0) @binding(1) var samp : sampler;
-// adjustments[0] -> brightness pct (if > 0)
-// adjustments[1] -> saturation pct (if > 0)
-@group(0) @binding(2) var<uniform> adjustments: vec2f;
-// R, G, B, A pct modifiers
-@group(0) @binding(3) var<uniform> chans: vec4f;
+@group(0) @binding(2) var lut_tex : texture_3d<f32>;
+@group(0) @binding(3) var lut_samp : sampler;
+@group(0) @binding(4) var<uniform> lut : LutParams;
+
+// How shapes combine with the pixels beneath them, set per render
+// pipeline: 0 normal, 1 multiply, 2 screen, 3 overlay, 4 add, 5 difference
+override blend_mode: u32 = 0;
+
+// Maps colours to lut texture coordinates, as `colour * scale + offset`
+struct LutParams {
+	scale: vec4f,
+	offset: vec4f,
+}
+
+// Rows of an affine colour matrix, then the exponent channels are raised
+// to. A colour stage runs on through the following entries while `more`
+// is 1.
+struct ColourEntry {
+	r: vec4f,
+	g: vec4f,
+	b: vec4f,
+	exponent: f32,
+	more: u32,
+}
+
+@group(0) @binding(5) var<storage, read> colours : array<ColourEntry>;
 
 fn hsv_to_rgb(hsv: vec3f) -> vec3f {
 	let h = hsv.r;
@@ -74,22 +95,105 @@ fn rgb_to_hsv(rgb: vec3f) -> vec3f {
 struct VertexIn {
   @location(0) position : vec2f,
 	@location(1) tex_coord: vec2f,
+	// adjustments[0] -> brightness pct (if > 0)
+	// adjustments[1] -> saturation pct (if > 0)
+	@location(2) adjustments: vec2f,
+	// R, G, B, A pct modifiers
+	@location(3) chans: vec4f,
+	// pixelate[0] -> mosaic block size in source pixels (if > 0)
+	// pixelate[1] -> 0 for square blocks, 1 for hexagons
+	@location(4) pixelate: vec2f,
+	// index of the first entry of the colour stage in `colours`
+	@location(5) colour: u32,
+	// how much of the lut's grade to mix in (if > 0)
+	@location(6) lut_strength: f32,
+	// how much of the blended colour to mix over the destination
+	@location(7) opacity: f32,
 }
 
 struct VertexOut {
   @builtin(position) position : vec4f,
 	@location(0) tex_coord: vec2f,
+	@location(1) @interpolate(flat) adjustments: vec2f,
+	@location(2) @interpolate(flat) chans: vec4f,
+	@location(3) @interpolate(flat) pixelate: vec2f,
+	@location(4) @interpolate(flat) colour: u32,
+	@location(5) @interpolate(flat) lut_strength: f32,
+	@location(6) @interpolate(flat) opacity: f32,
 }
 
 @vertex fn vert_main(in : VertexIn) -> VertexOut {
   var out : VertexOut;
   out.position = vec4f(in.position, 0., 1.);
 	out.tex_coord = in.tex_coord;
+	out.adjustments = in.adjustments;
+	out.chans = in.chans;
+	out.pixelate = in.pixelate;
+	out.colour = in.colour;
+	out.lut_strength = in.lut_strength;
+	out.opacity = in.opacity;
   return out;
 }
 
+// Floored modulo, unlike `%`, so negative coords tile the same way
+fn modulo(a: vec2f, b: vec2f) -> vec2f {
+	return a - b * floor(a / b);
+}
+
+// Center of the mosaic block containing `coord`, all in source pixels.
+// Hexagons are pointy-topped, with centers on two interleaved grids.
+fn block_center(coord: vec2f, size: f32, hex: bool) -> vec2f {
+	if !hex {
+			return (floor(coord / size) + 0.5) * size;
+	}
+
+	let p = coord / size;
+	let spacing = vec2f(1., sqrt(3.));
+	let half = spacing * 0.5;
+	let a = modulo(p, spacing) - half;
+	let b = modulo(p - half, spacing) - half;
+	if dot(a, a) < dot(b, b) {
+			return (p - a) * size;
+	}
+	return (p - b) * size;
+}
+
+// `src` drawn over `dst` in the pipeline's blend mode
+fn blend(src: vec3f, dst: vec3f) -> vec3f {
+	switch blend_mode {
+			case 1u: {
+				return src * dst;
+			}
+			case 2u: {
+				return 1. - (1. - src) * (1. - dst);
+			}
+			case 3u: {
+				let low = 2. * src * dst;
+				let high = 1. - 2. * (1. - src) * (1. - dst);
+				return select(high, low, dst < vec3f(0.5));
+			}
+			case 4u: {
+				return min(src + dst, vec3f(1.));
+			}
+			case 5u: {
+				return abs(src - dst);
+			}
+			case 0u, default {
+				return src;
+			}
+	}
+}
+
 @fragment fn frag_main(pos : VertexOut) -> @location(0) vec4f {
-	var color = textureSample(input_tex, samp, pos.tex_coord);
+	var tex_coord = pos.tex_coord;
+	if pos.pixelate.x > 0. {
+			let dims = vec2f(textureDimensions(input_tex));
+			tex_coord = block_center(tex_coord * dims, pos.pixelate.x, pos.pixelate.y > 0.5) / dims;
+	}
+
+	var color = textureSample(input_tex, samp, tex_coord);
+	let adjustments = pos.adjustments;
+	let chans = pos.chans;
 
 	if chans.r >= 0. || chans.g >= 0. || chans.b >= 0. || chans.a >= 0. {
 			color *= chans;
@@ -97,20 +201,52 @@ struct VertexOut {
 			color = min(color, vec4f(1., 1., 1., 1.));
 	}
 
-	if adjustments.x < 0. && adjustments.y < 0. {
-			return color;
+	if adjustments.x >= 0. || adjustments.y >= 0. {
+			var hsv = rgb_to_hsv(color.rgb);
+			if adjustments.x >= 0. {
+					// brightness
+					hsv.b = min(hsv.b * adjustments.x, 1.0);
+			}
+
+			if adjustments.y >= 0. {
+					// saturation
+					hsv.g = min(hsv.g * adjustments.y, 1.0);
+			}
+			color = vec4f(hsv_to_rgb(hsv), color.a);
+	}
+
+	// colour stage, an identity unless there are colour operations
+	var graded = color.rgb;
+	var i = pos.colour;
+	loop {
+			let entry = colours[i];
+			let rgb = vec4f(graded, 1.);
+			graded = vec3f(dot(entry.r, rgb), dot(entry.g, rgb), dot(entry.b, rgb));
+			graded = clamp(graded, vec3f(0.), vec3f(1.));
+			if entry.exponent != 1. {
+					// pow is undefined for 0 on some GPUs
+					graded = pow(max(graded, vec3f(0.000001)), vec3f(entry.exponent));
+			}
+			if entry.more == 0u {
+					break;
+			}
+			i += 1u;
 	}
 
-	var hsv = rgb_to_hsv(color.rgb);
-	if adjustments.x >= 0. {
-			// brightness
-			hsv.b = min(hsv.b * adjustments.x, 1.0);
+	if pos.lut_strength > 0. {
+			let coord = graded * lut.scale.xyz + lut.offset.xyz;
+			let looked_up = textureSampleLevel(lut_tex, lut_samp, coord, 0.).rgb;
+			graded = mix(graded, looked_up, pos.lut_strength);
 	}
 
-	if adjustments.y >= 0. {
-			// saturation
-			hsv.g = min(hsv.g * adjustments.y, 1.0);
+	if blend_mode != 0u || pos.opacity < 1. {
+			// the output starts as a copy of the input, so the destination
+			// is the input at this fragment. Shapes drawn earlier in the pass
+			// aren't seen, so overlapping shapes replace rather than blend
+			// over each other.
+			let dst = textureLoad(input_tex, vec2i(pos.position.xy), 0).rgb;
+			graded = mix(dst, blend(graded, dst), pos.opacity);
 	}
 
-	return vec4f(hsv_to_rgb(hsv), color.a);
+	return vec4f(graded, color.a);
 }
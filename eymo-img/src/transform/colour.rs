@@ -0,0 +1,247 @@
+// A colour operation, composed with the others on a transform in the order
+// they're written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColourStep {
+    // degrees around the colour wheel
+    Hue(f32),
+    Contrast(f32),
+    Gamma(f32),
+    Invert,
+    Grayscale,
+    // 0. leaves colours as is, 1. is fully sepia toned
+    Sepia(f32),
+}
+
+// Affine transform of RGB colours, each row giving an output channel as
+// `[r, g, b, offset]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ColourMatrix(pub(crate) [[f32; 4]; 3]);
+
+// Colour steps folded into the shader's colour stage: a list of matrices,
+// each followed by raising channels to an exponent. Matrices fold into the
+// one before unless there's a gamma between them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ColourStage(pub(crate) Vec<(ColourMatrix, f32)>);
+
+// One of the stage's matrices and its exponent, as read by the shader from
+// a storage buffer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ColourEntry {
+    matrix: [[f32; 4]; 3],
+    exponent: f32,
+    // 1 if the next entry is part of the same stage
+    more: u32,
+    _pad: [f32; 2],
+}
+
+// Luma weights, as used by CSS filters
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+const SEPIA: ColourMatrix = ColourMatrix([
+    [0.393, 0.769, 0.189, 0.],
+    [0.349, 0.686, 0.168, 0.],
+    [0.272, 0.534, 0.131, 0.],
+]);
+
+impl ColourMatrix {
+    pub(crate) const IDENTITY: Self = Self([[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.]]);
+
+    fn diagonal(scale: f32, offset: f32) -> Self {
+        Self([
+            [scale, 0., 0., offset],
+            [0., scale, 0., offset],
+            [0., 0., scale, offset],
+        ])
+    }
+
+    // Rotation about the gray axis, matching CSS `hue-rotate`
+    fn hue(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self([
+            [
+                0.213 + cos * 0.787 - sin * 0.213,
+                0.715 - cos * 0.715 - sin * 0.715,
+                0.072 - cos * 0.072 + sin * 0.928,
+                0.,
+            ],
+            [
+                0.213 - cos * 0.213 + sin * 0.143,
+                0.715 + cos * 0.285 + sin * 0.140,
+                0.072 - cos * 0.072 - sin * 0.283,
+                0.,
+            ],
+            [
+                0.213 - cos * 0.213 - sin * 0.787,
+                0.715 - cos * 0.715 + sin * 0.715,
+                0.072 + cos * 0.928 + sin * 0.072,
+                0.,
+            ],
+        ])
+    }
+
+    fn grayscale() -> Self {
+        let [r, g, b] = LUMA;
+        Self([[r, g, b, 0.]; 3])
+    }
+
+    fn lerp(self, o: Self, amount: f32) -> Self {
+        let mut m = self.0;
+        for (row, o_row) in m.iter_mut().zip(o.0) {
+            for (v, o_v) in row.iter_mut().zip(o_row) {
+                *v += (o_v - *v) * amount;
+            }
+        }
+        Self(m)
+    }
+
+    // This followed by `o`
+    fn then(self, o: Self) -> Self {
+        let a = self.0;
+        Self(o.0.map(|row| {
+            let mut out = [0., 0., 0., row[3]];
+            for (j, v) in out.iter_mut().enumerate() {
+                *v += (0..3).map(|k| row[k] * a[k][j]).sum::<f32>();
+            }
+            out
+        }))
+    }
+}
+
+impl ColourStep {
+    fn matrix(&self) -> Option<ColourMatrix> {
+        match *self {
+            ColourStep::Hue(degrees) => Some(ColourMatrix::hue(degrees)),
+            ColourStep::Contrast(f) => Some(ColourMatrix::diagonal(f, 0.5 * (1. - f))),
+            ColourStep::Invert => Some(ColourMatrix::diagonal(-1., 1.)),
+            ColourStep::Grayscale => Some(ColourMatrix::grayscale()),
+            ColourStep::Sepia(amount) => {
+                Some(ColourMatrix::IDENTITY.lerp(SEPIA, amount.clamp(0., 1.)))
+            }
+            ColourStep::Gamma(_) => None,
+        }
+    }
+}
+
+impl ColourStage {
+    pub(crate) fn new(steps: &[ColourStep]) -> Self {
+        let mut stage = Vec::from([(ColourMatrix::IDENTITY, 1.)]);
+        for step in steps {
+            let (matrix, exponent) = stage.last_mut().expect("stage is never empty");
+            match (step.matrix(), step) {
+                (Some(m), _) if *exponent != 1. => stage.push((m, 1.)),
+                (Some(m), _) => *matrix = matrix.then(m),
+                // gamma(2) brightens, raising channels to 1/2
+                (None, ColourStep::Gamma(g)) => *exponent /= g.max(f32::EPSILON),
+                (None, _) => {}
+            }
+        }
+        Self(stage)
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = ColourEntry> {
+        let last = self.0.len() - 1;
+        self.0
+            .iter()
+            .enumerate()
+            .map(move |(i, (matrix, exponent))| ColourEntry {
+                matrix: matrix.0,
+                exponent: *exponent,
+                more: (i < last) as u32,
+                _pad: [0., 0.],
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The colour stage as run in the shader, rounded
+    fn apply(steps: &[ColourStep], rgb: [f32; 3]) -> [f32; 3] {
+        let matrix = |m: ColourMatrix, c: [f32; 3]| {
+            m.0.map(|[r, g, b, offset]| (r * c[0] + g * c[1] + b * c[2] + offset).clamp(0., 1.))
+        };
+        let graded = ColourStage::new(steps)
+            .0
+            .into_iter()
+            .fold(rgb, |c, (m, exponent)| {
+                matrix(m, c).map(|v| v.powf(exponent))
+            });
+        graded.map(|v| (v * 1000.).round() / 1000.)
+    }
+
+    #[test]
+    fn colour_steps() {
+        assert_eq!(apply(&[], [0.2, 0.4, 0.6]), [0.2, 0.4, 0.6]);
+        assert_eq!(
+            apply(&[ColourStep::Invert], [0.2, 0.4, 0.6]),
+            [0.8, 0.6, 0.4]
+        );
+        assert_eq!(
+            apply(&[ColourStep::Contrast(2.)], [0.2, 0.5, 0.9]),
+            [0., 0.5, 1.]
+        );
+        assert_eq!(
+            apply(&[ColourStep::Gamma(2.)], [0.25, 1., 0.]),
+            [0.5, 1., 0.]
+        );
+        assert_eq!(
+            apply(&[ColourStep::Hue(360.)], [0.2, 0.4, 0.6]),
+            [0.2, 0.4, 0.6]
+        );
+        assert_eq!(
+            apply(&[ColourStep::Grayscale], [1., 0., 0.]),
+            [0.213, 0.213, 0.213]
+        );
+        assert_eq!(
+            apply(&[ColourStep::Sepia(0.)], [0.2, 0.4, 0.6]),
+            [0.2, 0.4, 0.6]
+        );
+        assert_eq!(
+            apply(&[ColourStep::Sepia(1.)], [1., 1., 1.]),
+            [1., 1., 0.937]
+        );
+    }
+
+    #[test]
+    fn colour_steps_compose_in_order() {
+        let steps = [ColourStep::Sepia(1.), ColourStep::Invert];
+        assert_eq!(apply(&steps, [1., 1., 1.]), [0., 0., 0.063]);
+        let steps = [ColourStep::Invert, ColourStep::Sepia(1.)];
+        assert_eq!(apply(&steps, [1., 1., 1.]), [0., 0., 0.]);
+
+        // gamma before or after inverting
+        let steps = [ColourStep::Gamma(2.), ColourStep::Invert];
+        assert_eq!(apply(&steps, [0.36, 0.36, 0.36]), [0.4, 0.4, 0.4]);
+        let steps = [ColourStep::Invert, ColourStep::Gamma(2.)];
+        assert_eq!(apply(&steps, [0.36, 0.36, 0.36]), [0.8, 0.8, 0.8]);
+
+        // neighbouring gammas multiply
+        let steps = [ColourStep::Gamma(2.), ColourStep::Gamma(2.)];
+        assert_eq!(apply(&steps, [0.0625, 0., 1.]), [0.5, 0., 1.]);
+        assert_eq!(ColourStage::new(&steps).0.len(), 1);
+
+        // and any number are kept apart by other steps
+        let steps = [
+            ColourStep::Gamma(2.),
+            ColourStep::Invert,
+            ColourStep::Gamma(2.),
+        ];
+        assert_eq!(apply(&steps, [0.36, 0.36, 0.36]), [0.632, 0.632, 0.632]);
+        let steps = [
+            ColourStep::Invert,
+            ColourStep::Gamma(2.),
+            ColourStep::Invert,
+            ColourStep::Gamma(0.5),
+            ColourStep::Sepia(0.),
+        ];
+        let stage = ColourStage::new(&steps);
+        assert_eq!(stage.0.len(), 3);
+        assert_eq!(
+            stage.entries().map(|e| e.more).collect::<Vec<_>>(),
+            [1, 1, 0]
+        );
+        assert_eq!(apply(&steps, [0.19, 0.19, 0.19]), [0.01, 0.01, 0.01]);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::docs;
+use eymo_img::lang::{
+    self, LintOptions, ParseError, Resolver, Severity, ast::FacePart, ast::Selector,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, Documentation, Hover,
+    HoverContents, InsertTextFormat, MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+
+// Parse errors and lint findings for a document. `path` is the document's
+// file, if it has one, which includes are resolved relative to.
+pub fn diagnostics(
+    text: &str,
+    path: Option<&str>,
+    resolver: &mut impl Resolver,
+) -> Vec<Diagnostic> {
+    let options = LintOptions::default();
+    let result = match path {
+        Some(path) => lang::lint_file(path, resolver, &options),
+        None => lang::lint(text, resolver, &options),
+    };
+
+    match result {
+        Ok(findings) => findings
+            .iter()
+            .map(|f| {
+                let severity = match f.severity {
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                };
+                diagnostic(text, path, &f.diagnostic, severity)
+            })
+            .collect(),
+        Err(e) => Vec::from([diagnostic(text, path, &e, DiagnosticSeverity::ERROR)]),
+    }
+}
+
+fn diagnostic(
+    text: &str,
+    path: Option<&str>,
+    e: &ParseError,
+    severity: DiagnosticSeverity,
+) -> Diagnostic {
+    let mut message = e.message.clone();
+    if let Some(suggestion) = &e.suggestion {
+        message.push_str(&format!(" (did you mean `{suggestion}`?)"));
+    }
+
+    // problems in included files are shown at the top of the document
+    let range = match &e.file {
+        Some(file) if Some(file.as_str()) != path => {
+            message = format!("{file}:{}:{}: {message}", e.line, e.column);
+            Range::default()
+        }
+        _ => Range::new(position(text, e.start), position(text, e.end)),
+    };
+
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        source: Some("eymo".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+// What can be written at a point in the source
+#[derive(Debug, PartialEq)]
+enum Context {
+    Shape,
+    Operation,
+    FlipVariant,
+    PixelateMode,
+    BlendMode,
+    Selector,
+}
+
+fn context(text: &str, offset: usize) -> Option<Context> {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &text[line_start..offset];
+    if line.contains("//") {
+        return None;
+    }
+    if line.trim_end_matches(is_word).ends_with('@') {
+        return Some(Context::Selector);
+    }
+
+    // innermost call the cursor is inside of
+    let mut depth = 0;
+    let mut braces = 0;
+    // arguments of the call before the cursor's
+    let mut commas = 0;
+    let mut call = None;
+    for (i, c) in line.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => {
+                call = Some(word_before(line, i));
+                break;
+            }
+            '(' => depth -= 1,
+            ',' if depth == 0 && braces == 0 => commas += 1,
+            // operations for a copy_to destination
+            '}' => braces += 1,
+            '{' if braces > 0 => braces -= 1,
+            '{' if depth == 0 && open_parens(&line[..i]) > 0 => {
+                return Some(Context::Operation);
+            }
+            _ => {}
+        }
+    }
+
+    let statement = line.trim_start();
+    match call {
+        Some("copy_to" | "swap_with" | "cycle" | "mouth_open" | "eye_open" | "blink") => {
+            Some(Context::Shape)
+        }
+        Some("flip") => Some(Context::FlipVariant),
+        Some("pixelate") if commas > 0 => Some(Context::PixelateMode),
+        Some("blend") => Some(Context::BlendMode),
+        // numeric arguments
+        Some(_) => None,
+        None if statement.starts_with("param ") => None,
+        None if statement.starts_with("let ") => {
+            statement.contains('=').then_some(Context::Operation)
+        }
+        None if line.contains(':') => Some(Context::Operation),
+        None => Some(Context::Shape),
+    }
+}
+
+fn open_parens(text: &str) -> usize {
+    let opened = text.matches('(').count();
+    opened.saturating_sub(text.matches(')').count())
+}
+
+fn word_before(line: &str, end: usize) -> &str {
+    let start = line[..end]
+        .rfind(|c: char| !is_word(c))
+        .map_or(0, |i| i + 1);
+    &line[start..end]
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn enum_members(members: &[&str]) -> Vec<CompletionItem> {
+    members
+        .iter()
+        .map(|member| CompletionItem {
+            label: member.to_string(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            ..Default::default()
+        })
+        .collect()
+}
+
+pub fn completions(text: &str, offset: usize) -> Vec<CompletionItem> {
+    match context(text, offset) {
+        Some(Context::Shape) => shape_completions(),
+        Some(Context::Operation) => docs::OPERATIONS
+            .iter()
+            .map(|op| CompletionItem {
+                label: op.keyword.to_string(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(op.signature()),
+                documentation: Some(markdown(op.doc.to_string())),
+                insert_text: Some(op.snippet()),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..Default::default()
+            })
+            .collect(),
+        Some(Context::FlipVariant) => enum_members(&["vertical", "horizontal", "both"]),
+        Some(Context::PixelateMode) => enum_members(&["grid", "hex"]),
+        Some(Context::BlendMode) => enum_members(&[
+            "normal",
+            "multiply",
+            "screen",
+            "overlay",
+            "add",
+            "difference",
+        ]),
+        Some(Context::Selector) => Selector::KEYWORDS
+            .into_iter()
+            .map(|selector| CompletionItem {
+                label: selector.to_string(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                insert_text: (selector == "nearest").then(|| docs::snippet(selector, &["x", "y"])),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..Default::default()
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn shape_completions() -> Vec<CompletionItem> {
+    let parts = FacePart::ALL.into_iter().map(|part| CompletionItem {
+        label: part.keyword().to_string(),
+        kind: Some(CompletionItemKind::VALUE),
+        detail: Some("face part".to_string()),
+        documentation: Some(markdown(docs::face_part(part).to_string())),
+        ..Default::default()
+    });
+    let shapes = docs::SHAPES.iter().map(|(name, args, doc)| CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::FUNCTION),
+        detail: Some(format!("{name}({})", args.join(", "))),
+        documentation: Some(markdown(doc.to_string())),
+        insert_text: Some(docs::snippet(name, args)),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        ..Default::default()
+    });
+    parts.chain(shapes).collect()
+}
+
+fn markdown(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+// Docs for the operation or face part under the cursor
+pub fn hover(text: &str, offset: usize) -> Option<Hover> {
+    let start = text[..offset]
+        .rfind(|c: char| !is_word(c))
+        .map_or(0, |i| i + 1);
+    let end = text[offset..]
+        .find(|c: char| !is_word(c))
+        .map_or(text.len(), |i| offset + i);
+    let word = &text[start..end];
+
+    let value = match docs::operation(word) {
+        Some(op) => op.markdown(),
+        None => {
+            let part = FacePart::ALL.into_iter().find(|p| p.keyword() == word)?;
+            format!("```eymo\n{word}\n```\n{}", docs::face_part(part))
+        }
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range::new(position(text, start), position(text, end))),
+    })
+}
+
+// Edit replacing the whole document with its canonical formatting, or
+// nothing if it doesn't parse or has comments formatting would drop
+pub fn format(text: &str) -> Option<Vec<TextEdit>> {
+    if lang::has_comments(text) {
+        return None;
+    }
+    let formatted = lang::format_source(text).ok()?;
+    let range = Range::new(Position::new(0, 0), position(text, text.len()));
+    Some(Vec::from([TextEdit::new(range, formatted)]))
+}
+
+// LSP position, with columns counted in UTF-16 code units, of a byte offset
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+// Byte offset of an LSP position
+pub fn offset(text: &str, position: Position) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].lines().next().unwrap_or("");
+
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lang::NoIncludes;
+
+    #[test]
+    fn positions() {
+        let text = "// héllo\nmouth: 😀\n";
+        for offset in [0, 3, 6, 10, 17, 21, text.len()] {
+            assert_eq!(offset, super::offset(text, position(text, offset)));
+        }
+        assert_eq!(position(text, 6), Position::new(0, 5));
+        assert_eq!(position(text, 21), Position::new(1, 9));
+        assert_eq!(position(text, text.len()), Position::new(2, 0));
+    }
+
+    #[test]
+    fn completion_context() {
+        let at_end = |text: &str| context(text, text.len());
+        assert_eq!(at_end(""), Some(Context::Shape));
+        assert_eq!(at_end("mouth: scale(2), "), Some(Context::Operation));
+        assert_eq!(at_end("mouth: scale("), None);
+        assert_eq!(at_end("mouth: copy_to(nose, "), Some(Context::Shape));
+        assert_eq!(
+            at_end("mouth: copy_to(rect(1, 2, 3, 4), "),
+            Some(Context::Shape)
+        );
+        assert_eq!(at_end("mouth: flip("), Some(Context::FlipVariant));
+        assert_eq!(at_end("mouth: pixelate("), None);
+        assert_eq!(at_end("mouth: pixelate(8, "), Some(Context::PixelateMode));
+        assert_eq!(at_end("mouth: blend("), Some(Context::BlendMode));
+        assert_eq!(
+            at_end("mouth: copy_to(leye { scale(2), "),
+            Some(Context::Operation)
+        );
+        assert_eq!(
+            at_end("mouth: copy_to(leye { flip(horizontal) }, "),
+            Some(Context::Shape)
+        );
+        assert_eq!(at_end("at 0s..5s { "), Some(Context::Shape));
+        assert_eq!(at_end("let pop = "), Some(Context::Operation));
+        assert_eq!(at_end("when blink("), Some(Context::Shape));
+        assert_eq!(at_end("mouth: tile // "), None);
+        assert_eq!(at_end("face@"), Some(Context::Selector));
+        assert_eq!(at_end("mouth: copy_to(nose@lef"), Some(Context::Selector));
+    }
+
+    #[test]
+    fn diagnostics_from_source() {
+        let text = "mouth: spin\nnose: scal(2)\n";
+        let found = diagnostics(text, None, &mut NoIncludes);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(found[0].range.start, Position::new(1, 6));
+
+        let text = "face: rotate(45), spin\n";
+        let found = diagnostics(text, None, &mut NoIncludes);
+        assert_eq!(found[0].severity, Some(DiagnosticSeverity::WARNING));
+
+        // numbers too large to use are errors, rather than taking the
+        // server down while they're typed
+        let text = "mouth#99999999999: spin";
+        let found = diagnostics(text, None, &mut NoIncludes);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(found[0].message, "number is too large");
+    }
+
+    #[test]
+    fn hover_docs() {
+        let text = "mouth: scale(2)";
+        let found = hover(text, 9).unwrap();
+        assert_eq!(
+            found.range,
+            Some(Range::new(Position::new(0, 7), Position::new(0, 12)))
+        );
+        assert!(hover(text, 2).is_some());
+        assert!(hover(text, 13).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use eymo_img::lang::ast::FacePart;
+
+pub struct OperationDoc {
+    pub keyword: &'static str,
+    pub args: &'static [&'static str],
+    pub doc: &'static str,
+}
+
+impl OperationDoc {
+    // How the operation is written, eg. `translate(x, y)`
+    pub fn signature(&self) -> String {
+        match self.args.is_empty() {
+            true => self.keyword.to_string(),
+            false => format!("{}({})", self.keyword, self.args.join(", ")),
+        }
+    }
+
+    // Completion snippet with a tab stop for each argument
+    pub fn snippet(&self) -> String {
+        if self.args.is_empty() {
+            return self.keyword.to_string();
+        }
+
+        snippet(self.keyword, self.args)
+    }
+
+    // Markdown shown on hover and alongside completions
+    pub fn markdown(&self) -> String {
+        format!("```eymo\n{}\n```\n{}", self.signature(), self.doc)
+    }
+}
+
+// Every operation in `Operation::KEYWORDS`, in the same order
+pub const OPERATIONS: [OperationDoc; 25] = [
+    OperationDoc {
+        keyword: "tile",
+        args: &[],
+        doc: "Tile the frame with copies of the shape.",
+    },
+    OperationDoc {
+        keyword: "scale",
+        args: &["factor"],
+        doc: "Grow or shrink the shape by a multiplication factor.",
+    },
+    OperationDoc {
+        keyword: "rotate",
+        args: &["degrees"],
+        doc: "Rotate the shape counter-clockwise by the given degrees.",
+    },
+    OperationDoc {
+        keyword: "copy_to",
+        args: &["shape", "..."],
+        doc: "Copy the shape to one or more destination shapes.",
+    },
+    OperationDoc {
+        keyword: "swap_with",
+        args: &["shape"],
+        doc: "Swap the shape's contents with another shape's contents.",
+    },
+    OperationDoc {
+        keyword: "cycle",
+        args: &["shape", "..."],
+        doc: "Move the shape's contents into the first shape, each shape's \
+              into the next, and the last one's back into the shape. With \
+              `period=2s` after the shapes, contents move on one shape every \
+              2 seconds.",
+    },
+    OperationDoc {
+        keyword: "translate",
+        args: &["x", "y"],
+        doc: "Move the shape by `x` pixels right and `y` pixels down.",
+    },
+    OperationDoc {
+        keyword: "flip",
+        args: &["vertical | horizontal | both"],
+        doc: "Flip the pixels in the shape.",
+    },
+    OperationDoc {
+        keyword: "drift",
+        args: &["velocity", "angle"],
+        doc: "Move the shape `velocity` pixels/second at `angle` degrees, \
+              bouncing off the edges of the frame. Defaults to `drift(0.5, 45)`.",
+    },
+    OperationDoc {
+        keyword: "spin",
+        args: &["rotations"],
+        doc: "Rotate the shape continuously, `rotations` full turns per \
+              second. Defaults to `spin(0.5)`.",
+    },
+    OperationDoc {
+        keyword: "brighten",
+        args: &["factor"],
+        doc: "Brighten or darken the shape by a factor, eg. `0.5` darkens by 50%.",
+    },
+    OperationDoc {
+        keyword: "saturate",
+        args: &["factor"],
+        doc: "Increase or decrease the saturation of the shape by a factor.",
+    },
+    OperationDoc {
+        keyword: "channels",
+        args: &["r", "g", "b"],
+        doc: "Scale the red, green and blue levels of the shape by separate factors.",
+    },
+    OperationDoc {
+        keyword: "reshape",
+        args: &["dxl", "dxr", "dyt", "dyb"],
+        doc: "Stretch the left, right, top and bottom halves of the shape by \
+              separate factors.",
+    },
+    OperationDoc {
+        keyword: "blur",
+        args: &["radius"],
+        doc: "Blur the pixels inside the shape, mixing in those up to `radius` \
+              pixels away.",
+    },
+    OperationDoc {
+        keyword: "pixelate",
+        args: &["block_size", "grid | hex"],
+        doc: "Draw the shape as a mosaic of blocks `block_size` pixels across \
+              in the source shape. Blocks are squares unless `hex` is given.",
+    },
+    OperationDoc {
+        keyword: "hue",
+        args: &["degrees"],
+        doc: "Shift the shape's colours around the colour wheel by the given degrees.",
+    },
+    OperationDoc {
+        keyword: "contrast",
+        args: &["factor"],
+        doc: "Increase or decrease the contrast of the shape by a factor.",
+    },
+    OperationDoc {
+        keyword: "gamma",
+        args: &["factor"],
+        doc: "Gamma correct the shape, eg. `2` brightens midtones and `0.5` \
+              darkens them.",
+    },
+    OperationDoc {
+        keyword: "invert",
+        args: &[],
+        doc: "Invert the shape's colours.",
+    },
+    OperationDoc {
+        keyword: "grayscale",
+        args: &[],
+        doc: "Turn the shape's colours to shades of gray.",
+    },
+    OperationDoc {
+        keyword: "sepia",
+        args: &["amount"],
+        doc: "Tone the shape sepia, from `0` for no change to `1` for fully sepia.",
+    },
+    OperationDoc {
+        keyword: "lut",
+        args: &["path", "strength"],
+        doc: "Grade the shape's colours with a `.cube` 3D LUT, mixed in by \
+              `strength` from `0` to `1` (the default). Paths are relative to the \
+              config file.",
+    },
+    OperationDoc {
+        keyword: "opacity",
+        args: &["amount"],
+        doc: "Draw the shape partly see-through, from `0` for invisible to `1` \
+              for fully opaque.",
+    },
+    OperationDoc {
+        keyword: "blend",
+        args: &["normal | multiply | screen | overlay | add | difference"],
+        doc: "Combine the shape with what it's drawn over, like layers in an \
+              image editor. `normal` draws over it.",
+    },
+];
+
+// `name(args)` with a tab stop for each argument, leaving out repeats
+pub fn snippet(name: &str, args: &[&str]) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .filter(|arg| **arg != "...")
+        .enumerate()
+        .map(|(i, arg)| format!("${{{}:{arg}}}", i + 1))
+        .collect();
+    format!("{name}({})", args.join(", "))
+}
+
+pub fn operation(keyword: &str) -> Option<&'static OperationDoc> {
+    OPERATIONS.iter().find(|op| op.keyword == keyword)
+}
+
+pub fn face_part(part: FacePart) -> &'static str {
+    match part {
+        FacePart::LEye => "Left eye",
+        FacePart::REye => "Right eye",
+        FacePart::LEyeRegion => "Left eye region, including the eyebrow",
+        FacePart::REyeRegion => "Right eye region, including the eyebrow",
+        FacePart::Face => "The whole face",
+        FacePart::Mouth => "Mouth",
+        FacePart::Nose => "Nose",
+        FacePart::Forehead => "Forehead",
+    }
+}
+
+// Builtin shapes other than face parts, as signature and description
+pub const SHAPES: [(&str, &[&str], &str); 2] = [
+    (
+        "rect",
+        &["x", "y", "width", "height"],
+        "Rectangle with its top-left corner at `x`, `y`. Any argument can \
+         be a percentage of the frame size.",
+    ),
+    (
+        "poly",
+        &["(x, y)", "..."],
+        "Polygon through the given points.",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eymo_img::lang::ast::Operation;
+
+    #[test]
+    fn every_operation_documented() {
+        let documented: Vec<&str> = OPERATIONS.iter().map(|op| op.keyword).collect();
+        assert_eq!(documented, Operation::KEYWORDS);
+    }
+
+    #[test]
+    fn snippets() {
+        assert_eq!(operation("tile").unwrap().snippet(), "tile");
+        assert_eq!(
+            operation("translate").unwrap().snippet(),
+            "translate(${1:x}, ${2:y})"
+        );
+        assert_eq!(
+            operation("translate").unwrap().signature(),
+            "translate(x, y)"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::Result;
+use eymo_img::lang::{FsResolver, Resolver};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, Formatting, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, HoverProviderCapability, OneOf,
+    PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    Url,
+};
+use std::collections::HashMap;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+mod analysis;
+mod docs;
+
+fn main() -> Result<()> {
+    // stdout is the LSP channel, so logs go to stderr
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(Vec::from([
+                ":".to_string(),
+                ",".to_string(),
+                "{".to_string(),
+                "@".to_string(),
+                "(".to_string(),
+            ])),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    })?;
+    connection.initialize(capabilities)?;
+    info!("eymo-lsp initialized");
+
+    // the connection has to be dropped for the io threads to finish
+    Server::default().run(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct Server {
+    // contents of open documents, which may not be saved yet
+    documents: HashMap<Url, String>,
+}
+
+impl Server {
+    fn run(&mut self, connection: Connection) -> Result<()> {
+        for msg in &connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    connection
+                        .sender
+                        .send(Message::Response(self.request(req)))?;
+                }
+                Message::Notification(notification) => {
+                    for uri in self.notification(notification) {
+                        let diagnostics = self.diagnostics(&uri);
+                        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+                        connection
+                            .sender
+                            .send(Message::Notification(Notification::new(
+                                PublishDiagnostics::METHOD.to_string(),
+                                params,
+                            )))?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, req: Request) -> Response {
+        match req.method.as_str() {
+            Completion::METHOD => handle::<Completion>(req, |params| {
+                let position = params.text_document_position;
+                let text = self.documents.get(&position.text_document.uri)?;
+                let offset = analysis::offset(text, position.position);
+                Some(CompletionResponse::Array(analysis::completions(
+                    text, offset,
+                )))
+            }),
+            HoverRequest::METHOD => handle::<HoverRequest>(req, |params| {
+                let position = params.text_document_position_params;
+                let text = self.documents.get(&position.text_document.uri)?;
+                analysis::hover(text, analysis::offset(text, position.position))
+            }),
+            Formatting::METHOD => handle::<Formatting>(req, |params| {
+                analysis::format(self.documents.get(&params.text_document.uri)?)
+            }),
+            method => {
+                warn!("Unhandled request {method}");
+                Response::new_err(
+                    req.id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unhandled request {method}"),
+                )
+            }
+        }
+    }
+
+    // Update open documents, returning those needing new diagnostics
+    fn notification(&mut self, notification: Notification) -> Vec<Url> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = params::<DidOpenTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                let doc = params.text_document;
+                self.documents.insert(doc.uri.clone(), doc.text);
+                Vec::from([doc.uri])
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = params::<DidChangeTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                // documents are synced in full, so the last change is the
+                // whole document
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), change.text);
+                // documents including this one may have changed too
+                self.documents.keys().cloned().collect()
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Some(params) = params::<DidCloseTextDocument>(notification) {
+                    self.documents.remove(&params.text_document.uri);
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn diagnostics(&self, uri: &Url) -> Vec<lsp_types::Diagnostic> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let path = uri
+            .to_file_path()
+            .ok()
+            .map(|p| p.to_string_lossy().to_string());
+        let mut resolver = OpenDocuments {
+            documents: &self.documents,
+        };
+        analysis::diagnostics(text, path.as_deref(), &mut resolver)
+    }
+}
+
+fn handle<R: lsp_types::request::Request>(
+    req: Request,
+    f: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(req.params) {
+        Ok(params) => Response::new_ok(req.id, f(params)),
+        Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+fn params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    serde_json::from_value(notification.params)
+        .inspect_err(|e| warn!("Invalid {} params: {e}", N::METHOD))
+        .ok()
+}
+
+// Reads includes from open documents where they have unsaved changes,
+// otherwise from disk
+struct OpenDocuments<'a> {
+    documents: &'a HashMap<Url, String>,
+}
+
+impl Resolver for OpenDocuments<'_> {
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        let open = Url::from_file_path(path)
+            .ok()
+            .and_then(|uri| self.documents.get(&uri));
+        match open {
+            Some(text) => Ok(text.clone()),
+            None => FsResolver.load(path),
+        }
+    }
+}
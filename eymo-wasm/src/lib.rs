@@ -5,6 +5,7 @@ use eymo_img::imggpu::gpu::GpuExecutor;
 use eymo_img::imggpu::resize::resize_texture;
 use eymo_img::lang;
 use eymo_img::pipeline::{Detection, Pipeline};
+use std::collections::HashMap;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
@@ -20,6 +21,10 @@ pub struct State {
 
 struct InnerState {
     interpreter: lang::Interpreter,
+    // sources available to `include` statements, keyed by path
+    includes: HashMap<String, String>,
+    // whether interpreters are run in strict mode
+    strict: bool,
     gpu: GpuExecutor,
     pipeline: Pipeline,
     canvas: web_sys::HtmlCanvasElement,
@@ -152,6 +157,8 @@ impl State {
 
         let inner_state = Mutex::new(InnerState {
             interpreter,
+            includes: HashMap::new(),
+            strict: false,
             gpu,
             pipeline,
             surface,
@@ -179,11 +186,92 @@ impl State {
         debug!("Setting command to {cmd}");
 
         let mut s = self.inner_state.lock().await;
-        let next_interpreter = wrap_err(lang::parse(cmd, &mut s.gpu))?;
-        s.interpreter = next_interpreter;
+        let statements = wrap_err(
+            lang::parse_statements_with(cmd, &mut s.includes).map_err(anyhow::Error::from),
+        )?;
+        s.interpreter = lang::Interpreter::new(statements, &mut s.gpu);
+        let strict = s.strict;
+        s.interpreter.set_strict(strict);
         Ok(())
     }
 
+    // Set the command from its JSON representation, as built by a form
+    // rather than written as source
+    #[wasm_bindgen]
+    pub async fn set_json_cmd(&self, json: &str) -> Result<(), JsValue> {
+        debug!("Setting JSON command to {json}");
+
+        let mut s = self.inner_state.lock().await;
+        let statements = wrap_err(lang::statements_from_json(json).map_err(anyhow::Error::from))?;
+        s.interpreter = lang::Interpreter::new(statements, &mut s.gpu);
+        let strict = s.strict;
+        s.interpreter.set_strict(strict);
+        Ok(())
+    }
+
+    // Change a `param` declared by the current command without rebuilding
+    // it, so animations carry on from where they are
+    #[wasm_bindgen]
+    pub async fn set_param(&self, name: &str, value: f32) -> Result<(), JsValue> {
+        let mut s = self.inner_state.lock().await;
+        wrap_err(s.interpreter.set_param(name, value))
+    }
+
+    // In strict mode each frame records the transforms that were skipped,
+    // or had operations ignored, for `report`
+    #[wasm_bindgen]
+    pub async fn set_strict(&self, strict: bool) {
+        let mut s = self.inner_state.lock().await;
+        s.strict = strict;
+        s.interpreter.set_strict(strict);
+    }
+
+    // What strict mode skipped on the last frame, as an array of
+    // { index, statement, reason, detail }, or null when not strict. `reason`
+    // is one of "missing_face", "missing_lut", "unsupported" or "timeout".
+    #[wasm_bindgen]
+    pub async fn report(&self) -> JsValue {
+        let s = self.inner_state.lock().await;
+        match s.interpreter.report() {
+            Some(report) => report
+                .skipped
+                .iter()
+                .map(skipped_to_js)
+                .collect::<js_sys::Array>()
+                .into(),
+            None => JsValue::NULL,
+        }
+    }
+
+    // Preload files for `include` statements in subsequent commands, from an
+    // object mapping paths to sources, eg. { "lib/pop.eymo": "let pop = ..." }
+    #[wasm_bindgen]
+    pub async fn set_includes(&self, files: js_sys::Object) -> Result<(), JsValue> {
+        let mut includes = HashMap::new();
+        for entry in js_sys::Object::entries(&files).iter() {
+            let entry = entry.unchecked_into::<js_sys::Array>();
+            match (entry.get(0).as_string(), entry.get(1).as_string()) {
+                (Some(path), Some(src)) => {
+                    includes.insert(path, src);
+                }
+                _ => return Err(JsValue::from_str("Includes must map paths to strings")),
+            }
+        }
+
+        self.inner_state.lock().await.includes = includes;
+        Ok(())
+    }
+
+    // Load a `.cube` file for `lut` operations, from its bytes, under the
+    // path commands refer to it by, eg. "film.cube"
+    #[wasm_bindgen]
+    pub async fn set_lut(&self, path: &str, bytes: Vec<u8>) -> Result<(), JsValue> {
+        let src = String::from_utf8(bytes)
+            .map_err(|_| JsValue::from_str(&format!("Lut {path} isn't valid UTF-8")))?;
+        let mut s = self.inner_state.lock().await;
+        wrap_err(s.gpu.load_lut(path, &src))
+    }
+
     #[wasm_bindgen]
     pub async fn stop(&self) -> Result<(), JsValue> {
         debug!("Stopping...");
@@ -427,9 +515,75 @@ impl State {
     }
 }
 
+// Exposes parse errors to JS as a plain object, eg:
+// { message, file, line, column, start, end, token, expected: [...], suggestion }
+// `start` and `end` are UTF-8 byte offsets into the source, so need
+// converting before use as JS string indices
+fn parse_error_to_js(e: &lang::ParseError) -> JsValue {
+    let obj = js_sys::Object::new();
+    let set = |k: &str, v: JsValue| {
+        js_sys::Reflect::set(&obj, &JsValue::from_str(k), &v).unwrap_throw();
+    };
+
+    set("name", JsValue::from_str("ParseError"));
+    set("message", JsValue::from_str(&e.message));
+    set(
+        "file",
+        e.file
+            .as_deref()
+            .map(JsValue::from_str)
+            .unwrap_or(JsValue::NULL),
+    );
+    set("line", JsValue::from(e.line));
+    set("column", JsValue::from(e.column));
+    set("start", JsValue::from(e.start as u32));
+    set("end", JsValue::from(e.end as u32));
+    set(
+        "token",
+        e.token
+            .as_deref()
+            .map(JsValue::from_str)
+            .unwrap_or(JsValue::NULL),
+    );
+    set(
+        "expected",
+        e.expected
+            .iter()
+            .map(|t| JsValue::from_str(t))
+            .collect::<js_sys::Array>()
+            .into(),
+    );
+    set(
+        "suggestion",
+        e.suggestion
+            .as_deref()
+            .map(JsValue::from_str)
+            .unwrap_or(JsValue::NULL),
+    );
+
+    obj.into()
+}
+
+fn skipped_to_js(skipped: &lang::Skipped) -> JsValue {
+    let obj = js_sys::Object::new();
+    let set = |k: &str, v: JsValue| {
+        js_sys::Reflect::set(&obj, &JsValue::from_str(k), &v).unwrap_throw();
+    };
+
+    set("index", JsValue::from(skipped.index as u32));
+    set("statement", JsValue::from_str(&skipped.statement));
+    set("reason", JsValue::from_str(skipped.reason.kind()));
+    set("detail", JsValue::from_str(&skipped.reason.to_string()));
+
+    obj.into()
+}
+
 fn wrap_err<T>(r: anyhow::Result<T>) -> Result<T, JsValue> {
     match r {
         Ok(t) => Ok(t),
-        Err(e) => Err(JsValue::from_str(&e.to_string())),
+        Err(e) => match e.downcast_ref::<Box<lang::ParseError>>() {
+            Some(pe) => Err(parse_error_to_js(pe)),
+            None => Err(JsValue::from_str(&e.to_string())),
+        },
     }
 }